@@ -0,0 +1,99 @@
+//! Compares the hand-written lexer with the regex-driven one it replaced.
+//! Run with `cargo bench`.
+
+extern crate tifflang;
+extern crate regex;
+
+mod regex_lexer;
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tifflang::lexer::Lexer;
+use regex_lexer::RegexLexer;
+
+/// A chunk of source that touches every kind of token, including a few
+/// mistakes so the error paths are measured too.
+const CHUNK: &str = r##"//! A module doc comment.
+import math.{square, cube}
+
+/* Block comments /* nest */ and
+   span lines. */
+/// Doc comments are tokens,
+//// unless they have four slashes.
+class Point {
+    ///
+    fn length(x: real, y: real) -> real {
+        x * x + y * y // squared, close enough
+    }
+}
+
+fn numbers(a: int, b: int) -> int {
+    let hex = 0xff_ff + 0b1010 + 0o17; let big = 2_147_483_647
+    let small = 42i32; let wide = 5_000_000_000i64
+    let real = 1.5e-3 + 2r + 6.02E23
+    if a { a - b } else { b / a }
+}
+
+fn strings() -> string {
+    let plain = "hello, world"
+    let escaped = "tab\there\nand \"quotes\" \u{1F600}"
+    let raw = r#"C:\dir "quoted""#
+    let größe = 名前 + é
+    let letters = 'a' as int + '\n' as int + '\u{1F600}' as int + '\''
+    plain
+}
+
+fn mistakes() -> int {
+    let bad = 0b102 + 12abc + "\q" + 99999999999i32 + 3000000000 + 9223372036854775808i64 + 5i128 + 0x + 0b_ + 1e_
+    let odd = 1 @ 2 # 3 é
+    let chars = 'ab' + '' + '\q' + 'open
+    bad
+}
+"##;
+
+const REPEATS: usize = 200;
+const ROUNDS: u32 = 5;
+
+fn time<F: FnMut() -> usize>(mut lex: F) -> (Duration, usize) {
+    let mut best = None;
+    let mut count = 0;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        count = lex();
+        let elapsed = start.elapsed();
+        if best.is_none_or(|best| elapsed < best) {
+            best = Some(elapsed);
+        }
+    }
+    (best.unwrap(), count)
+}
+
+fn report(name: &str, len: usize, (elapsed, count): (Duration, usize)) {
+    let seconds = elapsed.as_secs_f64();
+    println!(
+        "{:<8} {:>10.2} ms {:>10.1} MB/s {:>10} tokens",
+        name,
+        seconds * 1000.0,
+        len as f64 / seconds / 1_000_000.0,
+        count,
+    );
+}
+
+fn main() {
+    let source = Rc::new(CHUNK.repeat(REPEATS));
+    let lexer = Lexer::new();
+    let regex_lexer = RegexLexer::new();
+
+    let tokens = lexer.lex(source.clone());
+    let regex_tokens = regex_lexer.lex(source.clone());
+    assert_eq!(tokens.len(), regex_tokens.len(), "the lexers produced different numbers of tokens");
+    for (token, regex_token) in tokens.iter().zip(&regex_tokens) {
+        assert_eq!(token.range(), regex_token.range(), "the lexers disagree about where {:?} is", token);
+        assert_eq!(format!("{:?}", token.get_value()), format!("{:?}", regex_token.get_value()), "the lexers disagree");
+    }
+
+    println!("lexing {} bytes, best of {} rounds", source.len(), ROUNDS);
+    report("regex", source.len(), time(|| regex_lexer.lex(source.clone()).len()));
+    report("lex", source.len(), time(|| lexer.lex(source.clone()).len()));
+    report("tokens", source.len(), time(|| lexer.tokens(source.clone()).count()));
+}
@@ -0,0 +1,278 @@
+use regex::{Regex, RegexSet};
+use std::rc::Rc;
+use tifflang::span::Span;
+use tifflang::bench_support as support;
+use tifflang::lexer::{LexError, Token};
+
+/// The original lexer, which finds each token by matching a set of regexes
+/// against the rest of the input. Produces the same tokens as
+/// `lexer::Lexer`, much more slowly; it's kept to benchmark against. It
+/// predates interpolated strings, which it lexes as plain ones.
+pub struct RegexLexer {
+    whitespace: Regex,
+    set: RegexSet,
+    regexes: Vec<Regex>,
+}
+
+type LexResult = Result<Token, Span<LexError>>;
+
+struct Rule {
+    regex: &'static str,
+    process: fn(&[Span<&str>]) -> LexResult,
+}
+
+const REGEXES: &[Rule] = &[
+    Rule {
+        regex: r"^\(",
+        process: |_captures| -> LexResult {
+            Ok(Token::ParenLeft)
+        }
+    },
+    Rule {
+        regex: r"^\)",
+        process: |_captures| -> LexResult {
+            Ok(Token::ParenRight)
+        }
+    },
+    Rule {
+        regex: r"^\{",
+        process: |_captures| -> LexResult {
+            Ok(Token::CurlyLeft)
+        }
+    },
+    Rule {
+        regex: r"^\}",
+        process: |_captures| -> LexResult {
+            Ok(Token::CurlyRight)
+        }
+    },
+    Rule {
+        regex: r"^:",
+        process: |_captures| -> LexResult {
+            Ok(Token::Colon)
+        }
+    },
+    Rule {
+        regex: r"^\->",
+        process: |_captures| -> LexResult {
+            Ok(Token::Arrow)
+        }
+    },
+    Rule {
+        regex: r"^\+",
+        process: |_captures| -> LexResult {
+            Ok(Token::Plus)
+        }
+    },
+    Rule {
+        regex: r"^\-",
+        process: |_captures| -> LexResult {
+            Ok(Token::Minus)
+        }
+    },
+    Rule {
+        regex: r"^\*",
+        process: |_captures| -> LexResult {
+            Ok(Token::Aster)
+        }
+    },
+    Rule {
+        regex: r"^//[/!][^\n]*",
+        process: |captures| -> LexResult {
+            Ok(support::doc_comment(captures[0].get_value()).unwrap().1)
+        }
+    },
+    Rule {
+        regex: r"^/",
+        process: |_captures| -> LexResult {
+            Ok(Token::Slash)
+        }
+    },
+    Rule {
+        regex: r"^,",
+        process: |_captures| -> LexResult {
+            Ok(Token::Comma)
+        }
+    },
+    Rule {
+        regex: r"^;",
+        process: |_captures| -> LexResult {
+            Ok(Token::Semicolon)
+        }
+    },
+    Rule {
+        regex: r"^\.",
+        process: |_captures| -> LexResult {
+            Ok(Token::Dot)
+        }
+    },
+    Rule {
+        regex: r"^=",
+        process: |_captures| -> LexResult {
+            Ok(Token::Equals)
+        }
+    },
+
+    Rule {
+        regex: r"^([\p{XID_Start}_]\p{XID_Continue}*)",
+        process: |captures| -> LexResult {
+            Ok(match &*support::normalize(captures[1].get_value()) {
+                "class" => Token::Class,
+                "struct" => Token::Struct,
+                "fn" => Token::Fn,
+                "if" => Token::If,
+                "else" => Token::Else,
+                "let" => Token::Let,
+                "import" => Token::Import,
+                "as" => Token::As,
+                ident => Token::Ident(ident.to_owned()),
+            })
+        }
+    },
+    Rule {
+        regex: r"^(0x[0-9a-fA-F_]*|0b[0-9_]*|0o[0-9_]*|[0-9][0-9_]*(?:\.[0-9][0-9_]*)?(?:[eE][\-\+]?[0-9_]+)?)((?:[a-zA-Z][a-zA-Z0-9_]*)?)",
+        process: |captures| -> LexResult {
+            let literal = &captures[0];
+            support::number(literal.get_value(), captures[1].get_value().len())
+                .map_err(|(first, last, err)| literal.subspan(first, last).replace(err))
+        },
+    },
+    Rule {
+        regex: r#"^"((?:[^"\\]|\\(?s:.))*)""#,
+        process: |captures| -> LexResult {
+            support::unescape(captures[1].get_value())
+                .map(Token::String)
+                .map_err(|(first, last, err)| captures[1].subspan(first, last).replace(err))
+        }
+    },
+    Rule {
+        regex: r#"^"(?s:.*)"#,
+        process: |captures| -> LexResult {
+            Err(captures[0].subspan(0, 1).replace(LexError::UnterminatedString))
+        }
+    },
+    Rule {
+        regex: r"^'(?:[^'\\\n]|\\.)*'",
+        process: |captures| -> LexResult {
+            let literal = &captures[0];
+            support::char_literal(literal.get_value())
+                .map_err(|(first, last, err)| literal.subspan(first, last).replace(err))
+        }
+    },
+    Rule {
+        regex: r"^'[^\n]*",
+        process: |captures| -> LexResult {
+            Err(captures[0].subspan(0, 1).replace(LexError::UnterminatedChar))
+        }
+    },
+];
+
+impl Default for RegexLexer {
+    fn default() -> RegexLexer {
+        RegexLexer::new()
+    }
+}
+
+impl RegexLexer {
+    pub fn new() -> RegexLexer {
+        RegexLexer {
+            // comments starting with `///` or `//!` are doc comments, unless
+            // there are four slashes
+            whitespace: Regex::new(r"^(?:\s+|//(?:[^/!\n]|//)[^\n]*|//(?:\n|$))").unwrap(),
+            set: RegexSet::new(REGEXES.iter().map(|rule| rule.regex)).unwrap(),
+            regexes: REGEXES.iter().map(|rule| Regex::new(rule.regex).unwrap()).collect(),
+        }
+    }
+
+    pub fn lex(&self, input: Rc<String>) -> Vec<Span<Token>> {
+        let input_str = &input[..];
+        let mut index = 0;
+        let mut tokens = vec![];
+        loop {
+            if index >= input_str.len() {
+                break;
+            }
+            if let Some(capture) = self.whitespace.find(&input_str[index..]) {
+                index += capture.end();
+                continue;
+            }
+            if input_str[index..].starts_with("/*") {
+                match support::block_comment(&input_str[index..]) {
+                    Some(len) => index += len,
+                    None => {
+                        tokens.push(Span::new(
+                            Token::Error(LexError::UnterminatedComment),
+                            index, index + 2,
+                            input.clone()
+                        ));
+                        index = input_str.len();
+                    },
+                }
+                continue;
+            }
+            match support::raw_string(&input_str[index..]) {
+                Some(Ok((len, contents))) => {
+                    tokens.push(Span::new(
+                        Token::String(contents.to_owned()),
+                        index, index + len,
+                        input.clone()
+                    ));
+                    index += len;
+                    continue;
+                },
+                Some(Err(open)) => {
+                    tokens.push(Span::new(
+                        Token::Error(LexError::UnterminatedString),
+                        index, index + open,
+                        input.clone()
+                    ));
+                    index = input_str.len();
+                    continue;
+                },
+                None => (),
+            }
+            let matches = self.set.matches(&input_str[index..]);
+            let mut any_match = false;
+            for (i, rule) in REGEXES.iter().enumerate() {
+                if matches.matched(i) {
+                    let captures = self.regexes[i].captures(&input_str[index..]).unwrap();
+                    let first_capture = captures.get(0).unwrap();
+                    let captures: Vec<Span<&str>> = captures.iter().map(|capture| {
+                        let capture = capture.unwrap();
+                        Span::new(
+                            capture.as_str(),
+                            index + capture.start(),
+                            index + capture.end(),
+                            input.clone()
+                        )
+                    }).collect();
+                    let first = index + first_capture.start();
+                    let last = index + first_capture.end();
+                    index = last;
+                    tokens.push(match (rule.process)(&captures[..]) {
+                        Ok(token) => Span::new(token, first, last, input.clone()),
+                        Err(err) => err.map(Token::Error),
+                    });
+                    any_match = true;
+                    break;
+                }
+            }
+            if !any_match {
+                let ch = input_str[index..].chars().next().unwrap();
+                tokens.push(Span::new(
+                    Token::Error(LexError::UnexpectedCharacter(ch)),
+                    index, index + ch.len_utf8(),
+                    input.clone()
+                ));
+                index += ch.len_utf8();
+            }
+        }
+        tokens.push(Span::new(
+            Token::Eof,
+            input.len(),
+            input.len(),
+            input.clone()
+        ));
+        support::insert_newlines(input, tokens)
+    }
+}
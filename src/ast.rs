@@ -1,12 +1,54 @@
 use span::Span;
-use lexer::Token;
+use lexer::{LexError, Token};
 use typecheck::{Typed, Type};
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ParseError {
     UnexpectedToken {
         token: Span<Token>,
         expected: &'static str,
+    },
+    /// A statement starting on a new line with something that looks like
+    /// it should continue the line before.
+    AmbiguousLineStart {
+        token: Span<Token>,
+    },
+    /// The source ending before a bracket was closed.
+    UnexpectedEof {
+        unclosed: Span<Token>,
+    },
+}
+
+impl ParseError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ParseError::UnexpectedToken { ref token, .. } => match *token.get_value() {
+                Token::Error(ref err) => err.code(),
+                _ => "E0100",
+            },
+            ParseError::AmbiguousLineStart { .. } => "E0101",
+            ParseError::UnexpectedEof { .. } => "E0102",
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedToken { ref token, .. } if token.get_value().is_error() =>
+                write!(fmt, "{}", token.get_value()),
+            ParseError::UnexpectedToken { ref token, expected } =>
+                write!(fmt, "unexpected {}, expected {}", token.get_value(), expected),
+            ParseError::AmbiguousLineStart { ref token } => write!(
+                fmt,
+                "a statement can't start with {}; to continue the previous line, move it to the end of that line",
+                token.get_value()
+            ),
+            ParseError::UnexpectedEof { ref unclosed } =>
+                write!(fmt, "unexpected end of file, {} isn't closed", unclosed.get_value()),
+        }
     }
 }
 
@@ -18,12 +60,38 @@ pub enum BinOp {
     Div,
 }
 
+impl fmt::Display for BinOp {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+        };
+        write!(fmt, "{}", text)
+    }
+}
+
+/// A piece of an interpolated string.
+#[derive(Debug)]
+pub enum Fragment {
+    Text(String),
+    Hole(Span<Typed<Expr>>),
+}
+
 #[derive(Debug)]
 pub enum Expr {
     Error(ParseError),
     ConstInteger(i64),
+    ConstLong(i64),
     ConstNumber(f64),
     ConstString(String),
+    ConstChar(char),
+    /// A string like `"a {b} c"`, with the value of each hole written out
+    /// in its place.
+    Interpolated(Vec<Fragment>),
+    /// Names qualified by the module they come from are joined with dots,
+    /// as in `foo.bar`.
     Variable(String),
     BinOp {
         op: BinOp,
@@ -38,7 +106,12 @@ pub enum Expr {
         condition: Box<Span<Typed<Expr>>>,
         branch_then: Vec<Span<Typed<Expr>>>,
         branch_else: Vec<Span<Typed<Expr>>>,
-    }
+    },
+    /// A conversion like `c as int`.
+    Cast {
+        value: Box<Span<Typed<Expr>>>,
+        to: Span<Type>,
+    },
 }
 
 #[derive(Debug)]
@@ -52,6 +125,13 @@ pub enum Statement {
     Item(Item),
 }
 
+impl Statement {
+    /// Whether this is a doc comment on its own, which does nothing.
+    pub fn is_stray_doc(&self) -> bool {
+        matches!(*self, Statement::Item(Item::StrayDoc { .. }))
+    }
+}
+
 #[derive(Debug)]
 pub struct FunctionArgument {
     pub name: Span<String>,
@@ -61,20 +141,71 @@ pub struct FunctionArgument {
 #[derive(Debug)]
 pub enum Item {
     Error(ParseError),
+    /// A doc comment that isn't followed by an item, so it doesn't
+    /// document anything. It's kept so it can be warned about. `module` is
+    /// set for a `//!` comment that isn't at the top of its module.
+    StrayDoc {
+        module: bool,
+    },
     Function {
+        /// The text of the `///` comments before the function.
+        doc: Option<Span<String>>,
         name: Span<String>,
         args: Vec<Span<FunctionArgument>>,
         body: Vec<Span<Statement>>,
         result: Span<Type>,
     },
     Class {
+        doc: Option<Span<String>>,
         name: Span<String>,
         members: Vec<Span<Item>>,
     },
+    /// `import foo.bar` makes the items of `foo.bar` available as
+    /// `foo.bar.item`. `import foo.{a, b}` brings `a` and `b` into scope
+    /// directly.
+    Import {
+        path: Vec<Span<String>>,
+        names: Option<Vec<Span<String>>>,
+    },
 }
 
 #[derive(Debug)]
 pub struct Module {
     pub name: String,
+    /// The text of the `//!` comments at the top of the module.
+    pub doc: Option<Span<String>>,
     pub items: Vec<Span<Item>>,
+    /// Every lexing error in the module, including the ones the parser
+    /// skipped over while recovering from another mistake.
+    pub lex_errors: Vec<Span<LexError>>,
+}
+
+impl Module {
+    /// Finds a top-level function or class by name.
+    pub fn find_item(&self, name: &str) -> Option<&Span<Item>> {
+        self.items.iter().find(|item| match *item.get_value() {
+            Item::Function { name: ref item_name, .. } | Item::Class { name: ref item_name, .. } =>
+                item_name.get_value() == name,
+            _ => false,
+        })
+    }
+}
+
+/// A module together with everything it imports.
+#[derive(Debug)]
+pub struct Program {
+    /// Modules in dependency order, so the entry module is always last.
+    pub modules: Vec<Module>,
+    /// The files imported modules were loaded from, by module name.
+    pub files: HashMap<String, String>,
+}
+
+impl Program {
+    pub fn entry(&self) -> &Module {
+        self.modules.last().unwrap()
+    }
+
+    pub fn module(&self, name: &str) -> Option<&Module> {
+        self.modules.iter().find(|module| module.name == name)
+    }
 }
@@ -0,0 +1,41 @@
+//! Pieces of the lexer that the regex lexer in `benches/lexer` reuses, so
+//! that the two decode literals and insert newlines the same way. Not part
+//! of the library's API.
+
+use lexer::{self, LexError, Newlines, Token};
+use span::Span;
+use std::borrow::Cow;
+use std::rc::Rc;
+
+pub fn number(literal: &str, body_end: usize) -> Result<Token, (usize, usize, LexError)> {
+    lexer::number(literal, body_end)
+}
+
+pub fn unescape(text: &str) -> Result<String, (usize, usize, LexError)> {
+    lexer::unescape(text)
+}
+
+pub fn char_literal(literal: &str) -> Result<Token, (usize, usize, LexError)> {
+    lexer::char_literal(literal)
+}
+
+pub fn doc_comment(input: &str) -> Option<(usize, Token)> {
+    lexer::doc_comment(input)
+}
+
+pub fn raw_string(input: &str) -> Option<Result<(usize, &str), usize>> {
+    lexer::raw_string(input)
+}
+
+pub fn block_comment(input: &str) -> Option<usize> {
+    lexer::block_comment(input)
+}
+
+pub fn normalize<'a>(ident: &'a str) -> Cow<'a, str> {
+    lexer::normalize(ident)
+}
+
+/// Inserts the newline tokens that end statements, like `Lexer::lex` does.
+pub fn insert_newlines(input: Rc<String>, tokens: Vec<Span<Token>>) -> Vec<Span<Token>> {
+    Newlines::new(input, tokens.into_iter()).collect()
+}
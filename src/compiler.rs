@@ -1,118 +1,557 @@
 use wasm::builder::{FunctionBuilder, ModuleBuilder, CodeBuilder};
-use wasm::{ValueType, FuncType, Module as WasmModule, ExportEntry, ExportKind, FunctionIndex, LocalIndex};
-use ast::{Module, Expr, BinOp, Item, Statement};
+use wasm::{
+    ValueType, BlockType, FuncType, Module as WasmModule, ExportEntry, ExportKind, FunctionIndex,
+    FunctionSpaceIndex, LocalIndex, ImportEntry, ImportKind, ImportIndex, MemoryType, ResizableLimits,
+};
+use ast::{Module, Program, Expr, Fragment, BinOp, Item, Statement, ParseError};
+use resolver;
+use typecheck::{Typed, Type, TypeError};
+use span::Span;
 use std::collections::HashMap;
+use std::fmt;
 
-fn compile_expr(bindings: &HashMap<String, LocalIndex>, cb: CodeBuilder, expr: &Expr) -> CodeBuilder {
-    match *expr {
+#[derive(Debug)]
+pub enum CompileError {
+    ParseError(ParseError),
+    TypeError(TypeError),
+    UndefinedLocal {
+        name: String,
+    },
+    ResultMismatch {
+        expected: Type,
+        given: Type,
+    },
+    Unsupported {
+        feature: &'static str,
+    },
+}
+
+impl CompileError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            CompileError::ParseError(ref err) => err.code(),
+            CompileError::TypeError(ref err) => err.code(),
+            CompileError::UndefinedLocal { .. } => "E0301",
+            CompileError::Unsupported { .. } => "E0302",
+            CompileError::ResultMismatch { .. } => "E0303",
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CompileError::ParseError(ref err) => write!(fmt, "{}", err),
+            CompileError::TypeError(ref err) => write!(fmt, "{}", err),
+            CompileError::UndefinedLocal { ref name } => write!(fmt, "undefined local `{}`", name),
+            CompileError::ResultMismatch { ref expected, ref given } =>
+                write!(fmt, "function should return {}, but its body gives {}", expected, given),
+            CompileError::Unsupported { feature } => write!(fmt, "{} can't be compiled yet", feature),
+        }
+    }
+}
+
+type CompileResult = Result<CodeBuilder, Span<CompileError>>;
+
+fn unsupported(span: &Span<()>, feature: &'static str) -> Span<CompileError> {
+    span.replace(CompileError::Unsupported { feature })
+}
+
+/// The wasm type that values of a type are represented with, or `None` for
+/// void. Strings are handles to values the host keeps.
+fn value_type(span: &Span<()>, type_info: &Type) -> Result<Option<ValueType>, Span<CompileError>> {
+    match *type_info {
+        Type::Integer | Type::Boolean | Type::Char => Ok(Some(ValueType::I32)),
+        Type::Long => Ok(Some(ValueType::I64)),
+        Type::Real => Ok(Some(ValueType::F64)),
+        Type::Void => Ok(None),
+        Type::String => Ok(Some(ValueType::I32)),
+        Type::Function { .. } => Err(unsupported(span, "function values")),
+        Type::Error(ref err) => Err(span.replace(CompileError::TypeError(err.clone()))),
+    }
+}
+
+fn type_of(expr: &Typed<Expr>) -> Type {
+    expr.type_info.clone().unwrap_or(Type::Void)
+}
+
+/// Whether the assembler writes out a constant correctly. It stops its
+/// signed LEB128 encoding as soon as the rest of the bits match the sign,
+/// without checking that the last byte's sign bit does too, so values like
+/// 64 would be read back as negative.
+fn encodes_exactly(value: i64) -> bool {
+    let end = if value < 0 { -1 } else { 0 };
+    let mut rest = value;
+    while rest >> 7 != end {
+        rest >>= 7;
+    }
+    (rest & 0x40 != 0) == (value < 0)
+}
+
+/// Pushes an `i32` constant, building the ones the assembler can't write
+/// out from two halves that it can.
+fn constant_i32(cb: CodeBuilder, value: i32) -> CodeBuilder {
+    if encodes_exactly(i64::from(value)) {
+        return cb.constant(value);
+    }
+    let half = value >> 1;
+    let cb = constant_i32(cb, half);
+    constant_i32(cb, value - half).i32_add()
+}
+
+fn constant_i64(cb: CodeBuilder, value: i64) -> CodeBuilder {
+    if encodes_exactly(value) {
+        return cb.constant(value);
+    }
+    let half = value >> 1;
+    let cb = constant_i64(cb, half);
+    constant_i64(cb, value - half).i64_add()
+}
+
+/// The module host functions are imported from.
+pub const HOST_MODULE: &str = "env";
+
+/// The size of a page of wasm memory, in bytes.
+const PAGE_SIZE: u32 = 65536;
+
+/// Functions the host provides for working with strings, which compiled
+/// code only sees as `i32` handles. Each one is imported from
+/// `HOST_MODULE` the first time it's needed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum HostFunction {
+    /// Makes a string from the UTF-8 text at an offset and length in the
+    /// module's exported `memory`, which holds the string literals.
+    StringFromMemory,
+    Concat,
+    IntToString,
+    LongToString,
+    RealToString,
+    BoolToString,
+    CharToString,
+    /// Gives back its argument if it's a Unicode scalar value, and traps if
+    /// it isn't.
+    CheckChar,
+}
+
+impl HostFunction {
+    pub fn name(self) -> &'static str {
+        match self {
+            HostFunction::StringFromMemory => "string_from_memory",
+            HostFunction::Concat => "concat",
+            HostFunction::IntToString => "int_to_string",
+            HostFunction::LongToString => "long_to_string",
+            HostFunction::RealToString => "real_to_string",
+            HostFunction::BoolToString => "bool_to_string",
+            HostFunction::CharToString => "char_to_string",
+            HostFunction::CheckChar => "check_char",
+        }
+    }
+
+    fn signature(self) -> FuncType {
+        let params = match self {
+            HostFunction::StringFromMemory | HostFunction::Concat => vec![ValueType::I32, ValueType::I32],
+            HostFunction::IntToString | HostFunction::BoolToString | HostFunction::CharToString |
+            HostFunction::CheckChar => vec![ValueType::I32],
+            HostFunction::LongToString => vec![ValueType::I64],
+            HostFunction::RealToString => vec![ValueType::F64],
+        };
+        FuncType {
+            params,
+            ret: Some(ValueType::I32),
+        }
+    }
+}
+
+/// What compiled code needs from outside its own functions.
+#[derive(Default)]
+struct Runtime {
+    /// Host functions, in the order they're imported.
+    imports: Vec<HostFunction>,
+    /// The text of every string literal, one after another.
+    data: Vec<u8>,
+    /// Where each string literal's text is in `data`.
+    literals: HashMap<String, usize>,
+}
+
+impl Runtime {
+    fn call(&mut self, cb: CodeBuilder, function: HostFunction) -> CodeBuilder {
+        let index = match self.imports.iter().position(|&import| import == function) {
+            Some(index) => index,
+            None => {
+                self.imports.push(function);
+                self.imports.len() - 1
+            },
+        };
+        cb.call(FunctionSpaceIndex::Import(ImportIndex::new(index as u32)))
+    }
+
+    /// Makes a string from a literal, which is stored in memory.
+    fn literal(&mut self, cb: CodeBuilder, text: &str) -> CodeBuilder {
+        let data = &mut self.data;
+        let offset = *self.literals.entry(text.to_owned()).or_insert_with(|| {
+            data.extend_from_slice(text.as_bytes());
+            data.len() - text.len()
+        });
+        let cb = constant_i32(cb, offset as i32);
+        let cb = constant_i32(cb, text.len() as i32);
+        self.call(cb, HostFunction::StringFromMemory)
+    }
+
+    /// Adds the imports and memory that the compiled code used.
+    fn finish(self, md: &mut ModuleBuilder) {
+        for function in &self.imports {
+            let ty = md.add_type(function.signature());
+            md.add_import(ImportEntry {
+                module: HOST_MODULE.to_owned(),
+                field: function.name().to_owned(),
+                kind: ImportKind::Function(ty),
+            });
+        }
+        if self.imports.contains(&HostFunction::StringFromMemory) {
+            let pages = (self.data.len() as u32).div_ceil(PAGE_SIZE).max(1);
+            let memory = md.add_memory(MemoryType {
+                limits: ResizableLimits::new(pages),
+            });
+            md.new_data(memory, CodeBuilder::new().constant(0).end().build(), self.data);
+            md.add_export(ExportEntry {
+                field: "memory".to_owned(),
+                kind: ExportKind::Memory(memory),
+            });
+        }
+    }
+}
+
+struct Scope<'a> {
+    locals: HashMap<String, LocalIndex>,
+    functions: &'a HashMap<String, FunctionIndex>,
+    runtime: &'a mut Runtime,
+}
+
+/// Compiles a branch of an `if`, dropping every value but the last one.
+fn compile_branch(scope: &mut Scope, cb: CodeBuilder, exprs: &[Span<Typed<Expr>>], keep_last: bool) -> CompileResult {
+    let mut cb = cb;
+    for (index, expr) in exprs.iter().enumerate() {
+        cb = compile_expr(scope, cb, &expr.peek(), expr.get_value())?;
+        let is_last = index + 1 == exprs.len();
+        if !(is_last && keep_last) && value_type(&expr.peek(), &type_of(expr.get_value()))?.is_some() {
+            cb = cb.drop();
+        }
+    }
+    Ok(cb)
+}
+
+fn compile_expr(scope: &mut Scope, cb: CodeBuilder, span: &Span<()>, expr: &Typed<Expr>) -> CompileResult {
+    match expr.value {
         Expr::BinOp { ref op, ref lhs, ref rhs } => {
-            let cb = compile_expr(bindings, cb, lhs.get_value().get_value());
-            let cb = compile_expr(bindings, cb, rhs.get_value().get_value());
-            match *op {
-                BinOp::Add => cb.i32_add(),
-                BinOp::Sub => cb.i32_sub(),
-                BinOp::Mul => cb.i32_mul(),
-                BinOp::Div => unimplemented!(),
-            }
+            let operands = type_of(lhs.get_value());
+            let cb = compile_expr(scope, cb, &lhs.peek(), lhs.get_value())?;
+            let cb = compile_expr(scope, cb, &rhs.peek(), rhs.get_value())?;
+            Ok(match (operands, op) {
+                (Type::Real, &BinOp::Add) => cb.f64_add(),
+                (Type::Real, &BinOp::Sub) => cb.f64_sub(),
+                (Type::Real, &BinOp::Mul) => cb.f64_mul(),
+                (Type::Real, &BinOp::Div) => cb.f64_div(),
+                (Type::Long, &BinOp::Add) => cb.i64_add(),
+                (Type::Long, &BinOp::Sub) => cb.i64_sub(),
+                (Type::Long, &BinOp::Mul) => cb.i64_mul(),
+                (Type::Long, &BinOp::Div) => cb.i64_div_s(),
+                (_, &BinOp::Add) => cb.i32_add(),
+                (_, &BinOp::Sub) => cb.i32_sub(),
+                (_, &BinOp::Mul) => cb.i32_mul(),
+                (_, &BinOp::Div) => cb.i32_div_s(),
+            })
         },
         Expr::Variable(ref name) => {
-            if let Some(index) = bindings.get(name) {
-                cb.get_local(*index)
+            if let Some(index) = scope.locals.get(name) {
+                Ok(cb.get_local(*index))
+            }
+            else if scope.functions.contains_key(name) {
+                Err(unsupported(span, "function values"))
             }
             else {
-                panic!("Undefined local")
+                Err(span.replace(CompileError::UndefinedLocal {
+                    name: name.clone(),
+                }))
             }
         },
+        Expr::ConstInteger(value) if value < i64::from(i32::MIN) || value > i64::from(i32::MAX) => {
+            Err(unsupported(span, "integers wider than 32 bits"))
+        },
         Expr::ConstInteger(value) =>  {
-            cb.constant(value as i32)
+            Ok(constant_i32(cb, value as i32))
+        },
+        Expr::ConstLong(value) =>  {
+            Ok(constant_i64(cb, value))
+        },
+        Expr::ConstNumber(value) =>  {
+            Ok(cb.constant(value))
+        },
+        Expr::ConstChar(value) => {
+            Ok(constant_i32(cb, u32::from(value) as i32))
+        },
+        Expr::Cast { ref value, ref to } => {
+            value_type(span, &type_of(expr))?;
+            let cb = compile_expr(scope, cb, &value.peek(), value.get_value())?;
+            // chars are already their code point, but not every int is one
+            Ok(match (type_of(value.get_value()), to.get_value()) {
+                (Type::Integer, &Type::Char) => scope.runtime.call(cb, HostFunction::CheckChar),
+                (Type::Integer, &Type::Long) => cb.i64_extend_s_i32(),
+                (Type::Long, &Type::Integer) => cb.i32_wrap_i64(),
+                _ => cb,
+            })
         },
         Expr::If { ref condition, ref branch_then, ref branch_else } => {
-            let cb = compile_expr(bindings, cb, condition.get_value().get_value());
-            let cb = cb.if_();
+            // The builder can't give `if` a block type, so it's lowered to a
+            // pair of blocks instead.
+            let result = value_type(span, &type_of(expr))?;
+            let keep_last = result.is_some();
+            let cb = cb.block(BlockType(result)).block(BlockType(None));
+            let cb = compile_expr(scope, cb, &condition.peek(), condition.get_value())?;
+            let cb = cb.i32_eqz().br_if(0);
+            let cb = compile_branch(scope, cb, branch_then, keep_last)?;
+            let cb = cb.br(1).end();
+            let cb = compile_branch(scope, cb, branch_else, keep_last)?;
+            Ok(cb.end())
+        },
+        Expr::Call { ref func, ref args } => {
+            let index = match func.get_value().value {
+                Expr::Variable(ref name) if !scope.locals.contains_key(name) => scope.functions.get(name),
+                _ => None,
+            };
+            let index = match index {
+                Some(index) => *index,
+                None => return Err(unsupported(&func.peek(), "calls to function values")),
+            };
+            let mut cb = cb;
+            for arg in args {
+                cb = compile_expr(scope, cb, &arg.peek(), arg.get_value())?;
+            }
+            Ok(cb.call(FunctionSpaceIndex::Function(index)))
+        },
+        Expr::Error(ref e) => Err(span.replace(CompileError::ParseError(e.clone()))),
+        Expr::ConstString(ref value) => Ok(scope.runtime.literal(cb, value)),
+        Expr::Interpolated(ref fragments) => {
+            value_type(span, &type_of(expr))?;
+            // each piece is made into a string and joined onto the ones
+            // before it
             let mut cb = cb;
-            for expr in branch_then {
-                cb = compile_expr(bindings, cb, expr.get_value().get_value());
+            for (index, fragment) in fragments.iter().enumerate() {
+                cb = match *fragment {
+                    Fragment::Text(ref text) => scope.runtime.literal(cb, text),
+                    Fragment::Hole(ref hole) => {
+                        let cb = compile_expr(scope, cb, &hole.peek(), hole.get_value())?;
+                        match type_of(hole.get_value()) {
+                            Type::Integer => scope.runtime.call(cb, HostFunction::IntToString),
+                            Type::Long => scope.runtime.call(cb, HostFunction::LongToString),
+                            Type::Real => scope.runtime.call(cb, HostFunction::RealToString),
+                            Type::Boolean => scope.runtime.call(cb, HostFunction::BoolToString),
+                            Type::Char => scope.runtime.call(cb, HostFunction::CharToString),
+                            Type::String => cb,
+                            _ => return Err(unsupported(&hole.peek(), "this kind of value in a string")),
+                        }
+                    },
+                };
+                if index > 0 {
+                    cb = scope.runtime.call(cb, HostFunction::Concat);
+                }
             }
-            cb = cb.else_();
-            for expr in branch_else {
-                cb = compile_expr(bindings, cb, expr.get_value().get_value());
+            if fragments.is_empty() {
+                cb = scope.runtime.literal(cb, "");
             }
-            cb.end()
+            Ok(cb)
         },
-        Expr::Error(ref e) => panic!(format!("{:?}", e)),
-        _ => unimplemented!(),
     }
 }
 
-fn compile_statement(bindings: &HashMap<String, LocalIndex>, cb: CodeBuilder, stmt: &Statement) -> CodeBuilder {
+fn compile_statement(scope: &mut Scope, cb: CodeBuilder, span: &Span<()>, stmt: &Statement, local: Option<LocalIndex>) -> CompileResult {
     match *stmt {
-        Statement::Expr(ref expr) => compile_expr(&*bindings, cb, expr.get_value()),
+        Statement::Expr(ref expr) => compile_expr(scope, cb, span, expr),
         Statement::Let { ref name, ref value } => {
-            let index = bindings.get(name.get_value()).unwrap();
-            let cb = compile_expr(&*bindings, cb, value.get_value().get_value());
-            cb.set_local(*index)
+            let cb = compile_expr(scope, cb, &value.peek(), value.get_value())?;
+            let index = local.unwrap();
+            scope.locals.insert(name.get_value().clone(), index);
+            Ok(cb.set_local(index))
         },
-        Statement::Item(_) => unimplemented!(),
-        Statement::Error(ref e) => panic!(format!("{:?}", e)),
+        Statement::Item(Item::StrayDoc { .. }) => Ok(cb),
+        Statement::Item(_) => Err(unsupported(span, "nested items")),
+        Statement::Error(ref e) => Err(span.replace(CompileError::ParseError(e.clone()))),
     }
 }
 
-fn locals_statement(builder: &mut FunctionBuilder, bindings: &mut HashMap<String, LocalIndex>, stmt: &Statement) {
-    match *stmt {
-        Statement::Let { ref name, .. } => {
-            let index = builder.new_local(ValueType::I32);
-            bindings.insert(name.get_value().to_owned(), index);
-        },
-        _ => (),
+/// Each `let` gets its own local, so shadowed bindings can have different
+/// types.
+fn local_statement(builder: &mut FunctionBuilder, span: &Span<()>, stmt: &Statement) -> Result<Option<LocalIndex>, Span<CompileError>> {
+    if let Statement::Let { ref value, .. } = *stmt {
+        match value_type(&value.peek(), &type_of(value.get_value()))? {
+            Some(ty) => Ok(Some(builder.new_local(ty))),
+            None => Err(unsupported(span, "void bindings")),
+        }
+    }
+    else {
+        Ok(None)
     }
 }
 
-fn compile_item(md: &mut ModuleBuilder, stmt: &Item) {
-    match *stmt {
-        Item::Function { ref args, ref body, .. } => {
-            let ty = FuncType {
-                params: args.iter().map(|_| ValueType::I32).collect(),
-                ret: Some(ValueType::I32),
-            };
-            let mut bindings = HashMap::new();
-            let mut f = FunctionBuilder::new(ty);
+fn compile_function(md: &mut ModuleBuilder, runtime: &mut Runtime, functions: &HashMap<String, FunctionIndex>, item: &Item) -> Result<(), Span<CompileError>> {
+    let (args, body, result) = match *item {
+        Item::Function { ref args, ref body, ref result, .. } => (args, body, result),
+        _ => unreachable!(),
+    };
+
+    let mut params = vec![];
+    for arg in args {
+        let type_desc = &arg.get_value().type_desc;
+        match value_type(&type_desc.peek(), type_desc.get_value())? {
+            Some(ty) => params.push(ty),
+            None => return Err(unsupported(&type_desc.peek(), "void arguments")),
+        }
+    }
+    let ret = value_type(&result.peek(), result.get_value())?;
+    // doc comments that don't document anything can't be the result
+    let body: Vec<&Span<Statement>> = body.iter().filter(|stmt| !stmt.get_value().is_stray_doc()).collect();
+
+    let given = body.last().map(|stmt| match *stmt.get_value() {
+        Statement::Expr(ref expr) => type_of(expr),
+        _ => Type::Void,
+    }).unwrap_or(Type::Void);
+    if ret.is_some() && &given != result.get_value() {
+        return Err(result.replace(CompileError::ResultMismatch {
+            expected: result.get_value().clone(),
+            given,
+        }));
+    }
+    let returns_value = ret.is_some();
+
+    let mut f = FunctionBuilder::new(FuncType {
+        params,
+        ret,
+    });
+
+    let mut locals = vec![];
+    for stmt in &body {
+        locals.push(local_statement(&mut f, &stmt.peek(), stmt.get_value())?);
+    }
 
-            for stmt in body {
-                locals_statement(&mut f, &mut bindings, stmt.get_value());
+    let mut result = Ok(());
+    let f = f.code(|mut cb, params| {
+        let mut scope = Scope {
+            locals: HashMap::new(),
+            functions,
+            runtime,
+        };
+        for (index, param) in params.iter().enumerate() {
+            scope.locals.insert(
+                args[index].get_value().name.get_value().to_owned(),
+                *param
+            );
+        }
+
+        for (index, (stmt, local)) in body.iter().zip(locals).enumerate() {
+            let is_result = returns_value && index + 1 == body.len();
+            let compiled = compile_statement(&mut scope, cb, &stmt.peek(), stmt.get_value(), local)
+                .and_then(|cb| match *stmt.get_value() {
+                    Statement::Expr(ref expr) if !is_result => {
+                        let has_value = value_type(&stmt.peek(), &type_of(expr))?.is_some();
+                        Ok(if has_value { cb.drop() } else { cb })
+                    },
+                    _ => Ok(cb),
+                });
+            match compiled {
+                Ok(next) => cb = next,
+                Err(err) => {
+                    result = Err(err);
+                    return CodeBuilder::new();
+                },
             }
-            
-            let f = f.code(|mut cb, params| {
-                for (index, param) in params.iter().enumerate() {
-                    bindings.insert(
-                        args[index].get_value().name.get_value().to_owned(),
-                        *param
-                    );
-                }
+        }
+        cb.return_()
+    }).build();
+    result?;
+    md.new_function(f);
+    Ok(())
+}
 
-                for stmt in body {
-                    cb = compile_statement(&bindings, cb, stmt.get_value());
-                }
-                cb.return_()
-            }).build();
-            md.new_function(f);
-        },
-        _ => unimplemented!(),
+/// The functions of a module and the names they're exported as. Every
+/// function is exported under its own name, and the first one is also
+/// exported as `main` if nothing else is called that.
+pub fn exported_functions(module: &Module) -> Vec<(String, &Item)> {
+    let mut exports = vec![];
+    for item in &module.items {
+        if let Item::Function { ref name, .. } = *item.get_value() {
+            exports.push((name.get_value().clone(), item.get_value()));
+        }
     }
+    if !exports.is_empty() && !exports.iter().any(|(name, _)| name == "main") {
+        let first = exports[0].1;
+        exports.push(("main".to_owned(), first));
+    }
+    exports
 }
 
-pub fn compile_module(module: &Module) -> WasmModule {
+/// Compiles every module of a program into a single wasm module. Only the
+/// entry module's functions are exported. Errors are paired with the name
+/// of the module they're in.
+pub fn compile_program(program: &Program) -> Result<WasmModule, Vec<(String, Span<CompileError>)>> {
     let mut md = ModuleBuilder::new();
-    // function to create must be the 0th function of the module...
-    for item in &module.items {
-        compile_item(&mut md, item.get_value());
+    let mut runtime = Runtime::default();
+    let mut errors = vec![];
+
+    let mut indices = HashMap::new();
+    let mut count = 0;
+    for module in &program.modules {
+        for item in &module.items {
+            if let Item::Function { ref name, .. } = *item.get_value() {
+                indices.insert((module.name.clone(), name.get_value().clone()), FunctionIndex(count));
+                count += 1;
+            }
+        }
     }
 
-    md.add_export(ExportEntry {
-        field: "main".to_owned(),
-        kind: ExportKind::Function(FunctionIndex(0)),
-    });
+    for module in &program.modules {
+        // the functions each module can call, by the names it sees them as
+        let mut functions = HashMap::new();
+        for (name, target, item) in resolver::imported_items(module, program) {
+            if let Item::Function { name: ref function, .. } = *item {
+                functions.insert(name, indices[&(target.name.clone(), function.get_value().clone())]);
+            }
+        }
+        for item in &module.items {
+            if let Item::Function { ref name, .. } = *item.get_value() {
+                functions.insert(name.get_value().clone(), indices[&(module.name.clone(), name.get_value().clone())]);
+            }
+        }
+
+        for item in &module.items {
+            let result = match *item.get_value() {
+                Item::Function { .. } => compile_function(&mut md, &mut runtime, &functions, item.get_value()),
+                Item::Class { ref name, .. } => Err(unsupported(&name.peek(), "classes")),
+                Item::Import { .. } | Item::StrayDoc { .. } => Ok(()),
+                Item::Error(ref e) => Err(item.replace(CompileError::ParseError(e.clone()))),
+            };
+            if let Err(err) = result {
+                errors.push((module.name.clone(), err));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // the builder moves calls past the imported functions, but not exports
+    let imports = runtime.imports.len() as u32;
+    runtime.finish(&mut md);
 
-    let module = md.build();
+    let entry = program.entry();
+    for (field, item) in exported_functions(entry) {
+        if let Item::Function { ref name, .. } = *item {
+            let index = indices[&(entry.name.clone(), name.get_value().clone())];
+            md.add_export(ExportEntry {
+                field,
+                kind: ExportKind::Function(FunctionIndex(index.0 + imports)),
+            });
+        }
+    }
 
-    module
+    Ok(md.build())
 }
@@ -0,0 +1,275 @@
+use span::{Span, Position};
+use ast::{Expr, Fragment, ParseError, Statement, Item, Module, Program};
+use lexer::{self, LexError, Token};
+use typecheck::{Typed, Type};
+use std::fmt;
+use std::fmt::Write;
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(fmt, "error"),
+            Severity::Warning => write!(fmt, "warning"),
+        }
+    }
+}
+
+/// A problem found while compiling, ready to be shown to the user.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span<()>>,
+    /// The path of the file the span points into, when it's known.
+    pub file: Option<String>,
+}
+
+/// Used for failures that happen outside of the source, like a file that
+/// couldn't be read.
+pub const IO_ERROR: &str = "E0001";
+
+impl Diagnostic {
+    pub fn error<M: ToString>(code: &'static str, span: Option<Span<()>>, message: M) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span,
+            file: None,
+        }
+    }
+
+    pub fn warning<M: ToString>(code: &'static str, span: Option<Span<()>>, message: M) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, span, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Sets the file, unless one was already given.
+    pub fn in_file(mut self, file: &str) -> Diagnostic {
+        if self.file.is_none() {
+            self.file = Some(file.to_owned());
+        }
+        self
+    }
+
+    /// Renders the diagnostic as a single-line JSON object.
+    pub fn to_json(&self) -> String {
+        fn position(position: Position) -> String {
+            format!(r#"{{"line":{},"column":{}}}"#, position.line, position.column)
+        }
+
+        let mut out = String::new();
+        out.push('{');
+        match self.file {
+            Some(ref file) => write!(out, r#""file":{},"#, json_string(file)).unwrap(),
+            None => out.push_str(r#""file":null,"#),
+        }
+        match self.span {
+            Some(ref span) => {
+                let (first, last) = span.range();
+                write!(
+                    out, r#""range":{{"start":{},"end":{}}},"start":{},"end":{},"#,
+                    first, last, position(span.start()), position(span.end())
+                ).unwrap();
+            },
+            None => out.push_str(r#""range":null,"start":null,"end":null,"#),
+        }
+        write!(
+            out, r#""severity":"{}","code":"{}","message":{}}}"#,
+            self.severity, self.code, json_string(&self.message)
+        ).unwrap();
+        out
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut out = String::with_capacity(string.len() + 2);
+    out.push('"');
+    for ch in string.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut location = String::new();
+        if let Some(ref file) = self.file {
+            write!(location, "{}:", file)?;
+        }
+        if let Some(ref span) = self.span {
+            let start = span.start();
+            write!(location, "{}:{}:", start.line, start.column)?;
+        }
+        if !location.is_empty() {
+            location.push(' ');
+        }
+
+        let message = format!("{}{}[{}]: {}", location, self.severity, self.code, self.message);
+        match self.span {
+            Some(ref span) => write!(fmt, "{}", span.replace(message)),
+            None => writeln!(fmt, "{}", message),
+        }
+    }
+}
+
+/// Adds a syntax error, unless the same one was already reported at the
+/// same place. Skipping past one mistake can stop at the token it was
+/// about, which whatever is being parsed around it then reports again, and
+/// everything left open at the end of the file blames the same bracket.
+fn push_error<M: fmt::Display>(out: &mut Vec<Diagnostic>, code: &'static str, span: &Span<()>, message: M) {
+    let repeated = out.iter().any(|other| {
+        other.code == code && other.span.as_ref().is_some_and(|other| other.range() == span.range())
+    });
+    if !repeated {
+        out.push(Diagnostic::error(code, Some(span.clone()), message));
+    }
+}
+
+fn collect_parse_error(out: &mut Vec<Diagnostic>, span: &Span<()>, err: &ParseError) {
+    push_error(out, err.code(), span, err);
+}
+
+/// Adds the lexing errors that the parser didn't already report, like the
+/// ones it skipped over while recovering from another mistake.
+pub fn add_lex_errors(out: &mut Vec<Diagnostic>, errors: &[Span<LexError>]) {
+    for err in errors {
+        push_error(out, err.get_value().code(), &err.peek(), err.get_value());
+    }
+}
+
+fn collect_type(out: &mut Vec<Diagnostic>, span: &Span<()>, type_info: &Option<Type>) {
+    if let Some(Type::Error(ref err)) = *type_info {
+        if !err.is_cascade() {
+            out.push(Diagnostic::error(err.code(), Some(span.clone()), err));
+        }
+    }
+}
+
+fn collect_expr(out: &mut Vec<Diagnostic>, span: &Span<()>, expr: &Typed<Expr>) {
+    match expr.value {
+        Expr::Error(ref err) => collect_parse_error(out, span, err),
+        Expr::BinOp { ref lhs, ref rhs, .. } => {
+            collect_expr(out, &lhs.peek(), lhs.get_value());
+            collect_expr(out, &rhs.peek(), rhs.get_value());
+        },
+        Expr::Call { ref func, ref args } => {
+            collect_expr(out, &func.peek(), func.get_value());
+            for arg in args {
+                collect_expr(out, &arg.peek(), arg.get_value());
+            }
+        },
+        Expr::If { ref condition, ref branch_then, ref branch_else } => {
+            collect_expr(out, &condition.peek(), condition.get_value());
+            for expr in branch_then.iter().chain(branch_else) {
+                collect_expr(out, &expr.peek(), expr.get_value());
+            }
+        },
+        Expr::Cast { ref value, .. } => collect_expr(out, &value.peek(), value.get_value()),
+        Expr::Interpolated(ref fragments) => for fragment in fragments {
+            if let Fragment::Hole(ref expr) = *fragment {
+                collect_expr(out, &expr.peek(), expr.get_value());
+            }
+        },
+        _ => (),
+    }
+    collect_type(out, span, &expr.type_info);
+}
+
+fn collect_statement(out: &mut Vec<Diagnostic>, span: &Span<()>, stmt: &Statement) {
+    match *stmt {
+        Statement::Error(ref err) => collect_parse_error(out, span, err),
+        Statement::Expr(ref expr) => collect_expr(out, span, expr),
+        Statement::Let { ref value, .. } => collect_expr(out, &value.peek(), value.get_value()),
+        Statement::Item(ref item) => collect_item(out, span, item),
+    }
+}
+
+fn collect_item(out: &mut Vec<Diagnostic>, span: &Span<()>, item: &Item) {
+    match *item {
+        Item::Error(ref err) => collect_parse_error(out, span, err),
+        Item::Function { ref body, .. } => {
+            for stmt in body {
+                collect_statement(out, &stmt.peek(), stmt.get_value());
+            }
+        },
+        Item::Class { ref members, .. } => {
+            for member in members {
+                collect_item(out, &member.peek(), member.get_value());
+            }
+        },
+        Item::Import { .. } | Item::StrayDoc { .. } => (),
+    }
+}
+
+pub fn statement_diagnostics(stmt: &Span<Statement>) -> Vec<Diagnostic> {
+    let mut out = vec![];
+    collect_statement(&mut out, &stmt.peek(), stmt.get_value());
+    out
+}
+
+pub fn item_diagnostics(item: &Span<Item>) -> Vec<Diagnostic> {
+    let mut out = vec![];
+    collect_item(&mut out, &item.peek(), item.get_value());
+    out
+}
+
+/// Gathers the lexing errors in a list of tokens, including the ones in
+/// the holes of interpolated strings.
+pub fn collect_tokens(tokens: &[Span<Token>]) -> Vec<Diagnostic> {
+    let mut errors = vec![];
+    for token in tokens {
+        lexer::collect_errors(&mut errors, token);
+    }
+    let mut out = vec![];
+    add_lex_errors(&mut out, &errors);
+    out
+}
+
+/// Gathers the lexing errors in a module along with the parse and type
+/// errors recorded in its tree. Type errors that only exist because of
+/// another error are left out.
+pub fn collect_module(module: &Module) -> Vec<Diagnostic> {
+    let mut out = vec![];
+    for item in &module.items {
+        collect_item(&mut out, &item.peek(), item.get_value());
+    }
+    add_lex_errors(&mut out, &module.lex_errors);
+    out
+}
+
+/// Gathers the errors of every module in a program, pointing the ones in
+/// imported modules at the files they came from.
+pub fn collect_program(program: &Program) -> Vec<Diagnostic> {
+    let mut out = vec![];
+    for module in &program.modules {
+        let diagnostics = collect_module(module);
+        out.extend(diagnostics.into_iter().map(|diagnostic| match program.files.get(&module.name) {
+            Some(file) => diagnostic.in_file(file),
+            None => diagnostic,
+        }));
+    }
+    out
+}
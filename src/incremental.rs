@@ -0,0 +1,367 @@
+use ast::{Expr, Fragment, FunctionArgument, Item, Module, ParseError, Statement};
+use lexer::{self, LexError, Newlines, Scanner, StringPart, Token};
+use parser::{self, TokenIterator};
+use span::Span;
+use typecheck::{Type, Typed};
+use std::rc::Rc;
+
+/// Replaces the text between two byte offsets.
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// How much of a document had to be looked at again after an edit.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Reparsed {
+    /// The number of tokens lexed again.
+    pub tokens: usize,
+    /// The number of top-level items parsed again.
+    pub items: usize,
+}
+
+/// The module's doc comment, the items parsed and where each starts, and
+/// the index of the old item parsing stopped at, if any.
+type ParsedItems = (Option<Span<String>>, Vec<Span<Item>>, Vec<usize>, Option<usize>);
+
+/// A parsed source that can be edited without lexing and parsing all of it
+/// again. Tokens and items an edit can't have changed are kept, with their
+/// spans moved over to the new text.
+pub struct Document {
+    source: Rc<String>,
+    /// The tokens without newlines, which are added again when parsing
+    /// since where they go depends on the tokens around them.
+    tokens: Vec<Span<Token>>,
+    /// Where the first token of each item is.
+    starts: Vec<usize>,
+    module: Module,
+}
+
+impl Document {
+    pub fn new(name: &str, source: &str) -> Document {
+        let source = Rc::new(source.to_owned());
+        let mut document = Document {
+            tokens: Scanner::new(source.clone(), 0).collect(),
+            source,
+            starts: vec![],
+            module: Module {
+                name: name.to_owned(),
+                doc: None,
+                items: vec![],
+                lex_errors: vec![],
+            },
+        };
+        document.module.lex_errors = document.lex_errors();
+        let (doc, items, starts, _) = document.parse_items(0, &|_| None);
+        document.module.doc = doc;
+        document.module.items = items;
+        document.starts = starts;
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Parses items starting with the one at `offset`, until `reuse` gives
+    /// the index of an old item that can be kept in place of the next one.
+    /// Starting at the beginning also parses the module's doc comment.
+    fn parse_items(&self, offset: usize, reuse: &dyn Fn(usize) -> Option<usize>) -> ParsedItems {
+        let index = self.tokens.partition_point(|token| token.range().0 < offset);
+        let (before, after) = self.tokens.split_at(index);
+        let tokens = Newlines::resume(self.source.clone(), after.iter().cloned(), before);
+        let end = Span::new((), self.source.len(), self.source.len(), self.source.clone());
+        let mut iter = TokenIterator::new(tokens, end);
+
+        let doc = if offset == 0 { parser::parse_module_doc(&mut iter) } else { None };
+        let mut items = vec![];
+        let mut starts = vec![];
+        loop {
+            parser::skip_terminators(&mut iter);
+            let next = iter.peek();
+            if let Token::Eof = *next.get_value() {
+                return (doc, items, starts, None);
+            }
+            let start = next.range().0;
+            if let Some(old) = reuse(start) {
+                return (doc, items, starts, Some(old));
+            }
+            starts.push(start);
+            items.push(parser::parse_module_item(&mut iter));
+        }
+    }
+
+    /// Applies an edit, lexing and parsing only as much of the source again
+    /// as it could have changed. Panics if the edit's offsets are out of
+    /// bounds or not on character boundaries.
+    pub fn edit(&mut self, edit: &Edit) -> Reparsed {
+        let Edit { start, end, ref text } = *edit;
+        let mut new_source = String::with_capacity(self.source.len() - (end - start) + text.len());
+        new_source.push_str(&self.source[..start]);
+        new_source.push_str(text);
+        new_source.push_str(&self.source[end..]);
+        let source = Rc::new(new_source);
+        let delta = text.len() as isize - (end - start) as isize;
+        let shift = |offset: usize| (offset as isize + delta) as usize;
+
+        // Lexing a token looks one byte past its end, error tokens can
+        // swallow more than their span, and an `r` can turn out to start a
+        // raw string once the `#`s and `"` after it are there, so lexing
+        // starts again after the last token that's clear of all three.
+        let mut first = self.tokens.iter().position(|token| token.range().1 >= start).unwrap();
+        while first > 0 && restarts_before(self.tokens[first - 1].get_value()) {
+            first -= 1;
+        }
+        let restart = if first > 0 { self.tokens[first - 1].range().1 } else { 0 };
+
+        // Once a token starts where one did after the edit, the rest of the
+        // text is the same and lexes the same way.
+        let mut relexed = vec![];
+        let mut old = first;
+        for token in Scanner::new(source.clone(), restart) {
+            let token_start = token.range().0;
+            while self.tokens[old].range().0 < end || shift(self.tokens[old].range().0) < token_start {
+                old += 1;
+            }
+            let same = shift(self.tokens[old].range().0) == token_start &&
+                !token.get_value().is_error() && !self.tokens[old].get_value().is_error();
+            if same {
+                break;
+            }
+            relexed.push(token);
+        }
+        let relexed_len = relexed.len();
+        let resync = self.tokens[old].range().0;
+        let old_brackets = lexer::open_brackets(&self.tokens[..old]);
+
+        self.tokens.splice(first..old, relexed);
+        self.tokens[..first].rebase(&source, 0);
+        self.tokens[first + relexed_len..].rebase(&source, delta);
+        // how newlines are placed after the edit depends on the brackets
+        // still open, so items after it can only be kept if those match
+        let reusable = lexer::open_brackets(&self.tokens[..first + relexed_len]) == old_brackets;
+        self.source = source.clone();
+
+        // Parsing an item looks at the token after it, so it has to be
+        // parsed again if that one might have changed.
+        let kept = (0..self.starts.len())
+            .take_while(|&index| self.starts.get(index + 1).is_some_and(|&next| next < restart))
+            .count();
+        let offset = if kept == 0 { 0 } else { self.starts[kept] };
+        let (doc, items, starts, reused) = {
+            let old_starts = &self.starts;
+            self.parse_items(offset, &|start| {
+                if !reusable || start < shift(resync) {
+                    return None;
+                }
+                let old_start = (start as isize - delta) as usize;
+                old_starts.binary_search(&old_start).ok()
+            })
+        };
+        let reparsed = Reparsed {
+            tokens: relexed_len,
+            items: items.len(),
+        };
+
+        let (mut tail_items, mut tail_starts) = match reused {
+            Some(index) => (self.module.items.split_off(index), self.starts.split_off(index)),
+            None => (vec![], vec![]),
+        };
+        self.module.items.truncate(kept);
+        self.starts.truncate(kept);
+        if kept == 0 {
+            self.module.doc = doc;
+        }
+        else {
+            self.module.doc.rebase(&source, 0);
+        }
+        self.module.items.rebase(&source, 0);
+        tail_items.rebase(&source, delta);
+        for start in &mut tail_starts {
+            *start = shift(*start);
+        }
+        self.module.items.extend(items);
+        self.module.items.append(&mut tail_items);
+        self.starts.extend(starts);
+        self.starts.append(&mut tail_starts);
+        self.module.lex_errors = self.lex_errors();
+        reparsed
+    }
+
+    fn lex_errors(&self) -> Vec<Span<LexError>> {
+        let mut errors = vec![];
+        for token in &self.tokens {
+            lexer::collect_errors(&mut errors, token);
+        }
+        errors
+    }
+}
+
+/// Whether an edit after a token could change how it lexes, beyond the
+/// byte after it.
+fn restarts_before(token: &Token) -> bool {
+    match *token {
+        Token::Ident(ref ident) => ident == "r",
+        ref token => token.is_error(),
+    }
+}
+
+/// Moves every span in a tree over to an edited copy of its buffer.
+trait Rebase {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize);
+}
+
+impl<T: Rebase> Rebase for Span<T> {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        Span::relocate(self, buffer, delta);
+        self.value.rebase(buffer, delta);
+    }
+}
+
+impl<T: Rebase> Rebase for Typed<T> {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        self.value.rebase(buffer, delta);
+    }
+}
+
+impl<T: Rebase> Rebase for Box<T> {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        (**self).rebase(buffer, delta);
+    }
+}
+
+impl<T: Rebase> Rebase for [T] {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        for value in self {
+            value.rebase(buffer, delta);
+        }
+    }
+}
+
+impl<T: Rebase> Rebase for Vec<T> {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        self[..].rebase(buffer, delta);
+    }
+}
+
+impl<T: Rebase> Rebase for Option<T> {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        if let Some(ref mut value) = *self {
+            value.rebase(buffer, delta);
+        }
+    }
+}
+
+impl Rebase for String {
+    fn rebase(&mut self, _buffer: &Rc<String>, _delta: isize) {}
+}
+
+impl Rebase for Token {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        if let Token::InterpolatedString(ref mut parts) = *self {
+            for part in parts {
+                if let StringPart::Hole(ref mut tokens) = *part {
+                    tokens.rebase(buffer, delta);
+                }
+            }
+        }
+    }
+}
+
+impl Rebase for Type {
+    fn rebase(&mut self, _buffer: &Rc<String>, _delta: isize) {}
+}
+
+impl Rebase for ParseError {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        match *self {
+            ParseError::UnexpectedToken { ref mut token, .. } |
+            ParseError::AmbiguousLineStart { ref mut token } |
+            ParseError::UnexpectedEof { unclosed: ref mut token } => token.rebase(buffer, delta),
+        }
+    }
+}
+
+impl Rebase for Expr {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        match *self {
+            Expr::Error(ref mut err) => err.rebase(buffer, delta),
+            Expr::ConstInteger(_) | Expr::ConstLong(_) | Expr::ConstNumber(_) | Expr::ConstString(_) | Expr::ConstChar(_) |
+            Expr::Variable(_) => (),
+            Expr::BinOp { ref mut lhs, ref mut rhs, .. } => {
+                lhs.rebase(buffer, delta);
+                rhs.rebase(buffer, delta);
+            },
+            Expr::Call { ref mut func, ref mut args } => {
+                func.rebase(buffer, delta);
+                args.rebase(buffer, delta);
+            },
+            Expr::If { ref mut condition, ref mut branch_then, ref mut branch_else } => {
+                condition.rebase(buffer, delta);
+                branch_then.rebase(buffer, delta);
+                branch_else.rebase(buffer, delta);
+            },
+            Expr::Cast { ref mut value, ref mut to } => {
+                value.rebase(buffer, delta);
+                to.rebase(buffer, delta);
+            },
+            Expr::Interpolated(ref mut fragments) => for fragment in fragments {
+                if let Fragment::Hole(ref mut expr) = *fragment {
+                    expr.rebase(buffer, delta);
+                }
+            },
+        }
+    }
+}
+
+impl Rebase for Statement {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        match *self {
+            Statement::Error(ref mut err) => err.rebase(buffer, delta),
+            Statement::Expr(ref mut expr) => expr.rebase(buffer, delta),
+            Statement::Let { ref mut name, ref mut value } => {
+                name.rebase(buffer, delta);
+                value.rebase(buffer, delta);
+            },
+            Statement::Item(ref mut item) => item.rebase(buffer, delta),
+        }
+    }
+}
+
+impl Rebase for FunctionArgument {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        self.name.rebase(buffer, delta);
+        self.type_desc.rebase(buffer, delta);
+    }
+}
+
+impl Rebase for Item {
+    fn rebase(&mut self, buffer: &Rc<String>, delta: isize) {
+        match *self {
+            Item::Error(ref mut err) => err.rebase(buffer, delta),
+            Item::StrayDoc { .. } => (),
+            Item::Function { ref mut doc, ref mut name, ref mut args, ref mut body, ref mut result } => {
+                doc.rebase(buffer, delta);
+                name.rebase(buffer, delta);
+                args.rebase(buffer, delta);
+                body.rebase(buffer, delta);
+                result.rebase(buffer, delta);
+            },
+            Item::Class { ref mut doc, ref mut name, ref mut members } => {
+                doc.rebase(buffer, delta);
+                name.rebase(buffer, delta);
+                members.rebase(buffer, delta);
+            },
+            Item::Import { ref mut path, ref mut names } => {
+                path.rebase(buffer, delta);
+                names.rebase(buffer, delta);
+            },
+        }
+    }
+}
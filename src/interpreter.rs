@@ -0,0 +1,299 @@
+use ast::{Expr, Fragment, BinOp, Statement, Item};
+use span::Span;
+use typecheck::{Type, Typed};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::fmt;
+use std::mem;
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    args: Vec<String>,
+    body: Vec<Span<Statement>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i32),
+    Long(i64),
+    Real(f64),
+    String(String),
+    Boolean(bool),
+    Char(char),
+    Void,
+    Function(Rc<Function>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Integer(value) => write!(fmt, "{}", value),
+            Value::Long(value) => write!(fmt, "{}", value),
+            Value::Real(value) => write!(fmt, "{:?}", value),
+            Value::String(ref value) => write!(fmt, "{:?}", value),
+            Value::Boolean(value) => write!(fmt, "{}", value),
+            Value::Char(value) => write!(fmt, "{:?}", value),
+            Value::Void => write!(fmt, "()"),
+            Value::Function(ref func) => write!(fmt, "fn {}", func.name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RuntimeError {
+    DivisionByZero,
+    IntegerOverflow,
+    StackOverflow,
+    UndefinedVariable {
+        name: String,
+    },
+    CallingNonFunction,
+    InvalidOperands {
+        op: BinOp,
+    },
+    /// An int converted to a char that isn't a Unicode scalar value.
+    InvalidChar {
+        value: i32,
+    },
+    /// The code still had errors in it, which typechecking should have
+    /// caught before it got here.
+    InvalidCode,
+    Unsupported {
+        feature: &'static str,
+    },
+}
+
+impl RuntimeError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            RuntimeError::DivisionByZero => "E0401",
+            RuntimeError::IntegerOverflow => "E0402",
+            RuntimeError::StackOverflow => "E0403",
+            RuntimeError::UndefinedVariable { .. } => "E0404",
+            RuntimeError::CallingNonFunction => "E0405",
+            RuntimeError::InvalidOperands { .. } => "E0406",
+            RuntimeError::InvalidCode => "E0407",
+            RuntimeError::Unsupported { .. } => "E0408",
+            RuntimeError::InvalidChar { .. } => "E0409",
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RuntimeError::DivisionByZero => write!(fmt, "division by zero"),
+            RuntimeError::IntegerOverflow => write!(fmt, "integer overflow"),
+            RuntimeError::StackOverflow => write!(fmt, "too many nested calls"),
+            RuntimeError::UndefinedVariable { ref name } => write!(fmt, "undefined variable `{}`", name),
+            RuntimeError::CallingNonFunction => write!(fmt, "called something that isn't a function"),
+            RuntimeError::InvalidOperands { ref op } => write!(fmt, "invalid operands for `{}`", op),
+            RuntimeError::InvalidCode => write!(fmt, "can't evaluate code that has errors"),
+            RuntimeError::Unsupported { feature } => write!(fmt, "{} can't be evaluated yet", feature),
+            RuntimeError::InvalidChar { value } => write!(fmt, "{} isn't a valid character", value),
+        }
+    }
+}
+
+type EvalResult = Result<Value, Span<RuntimeError>>;
+type Locals = HashMap<String, Value>;
+
+const MAX_CALL_DEPTH: usize = 512;
+
+/// Evaluates typechecked code by walking its tree.
+#[derive(Default)]
+pub struct Interpreter {
+    functions: HashMap<String, Rc<Function>>,
+    globals: Locals,
+}
+
+/// Ints wrap around like they do in compiled code, where dividing by zero
+/// and the one division that overflows trap instead.
+fn arithmetic(span: &Span<()>, op: &BinOp, lhs: Value, rhs: Value) -> EvalResult {
+    match (lhs, rhs) {
+        (Value::Integer(lhs), Value::Integer(rhs)) => {
+            let value = match *op {
+                BinOp::Add => lhs.wrapping_add(rhs),
+                BinOp::Sub => lhs.wrapping_sub(rhs),
+                BinOp::Mul => lhs.wrapping_mul(rhs),
+                BinOp::Div if rhs == 0 => return Err(span.replace(RuntimeError::DivisionByZero)),
+                BinOp::Div => lhs.checked_div(rhs).ok_or_else(|| span.replace(RuntimeError::IntegerOverflow))?,
+            };
+            Ok(Value::Integer(value))
+        },
+        (Value::Long(lhs), Value::Long(rhs)) => {
+            let value = match *op {
+                BinOp::Add => lhs.wrapping_add(rhs),
+                BinOp::Sub => lhs.wrapping_sub(rhs),
+                BinOp::Mul => lhs.wrapping_mul(rhs),
+                BinOp::Div if rhs == 0 => return Err(span.replace(RuntimeError::DivisionByZero)),
+                BinOp::Div => lhs.checked_div(rhs).ok_or_else(|| span.replace(RuntimeError::IntegerOverflow))?,
+            };
+            Ok(Value::Long(value))
+        },
+        (Value::Real(lhs), Value::Real(rhs)) => Ok(Value::Real(match *op {
+            BinOp::Add => lhs + rhs,
+            BinOp::Sub => lhs - rhs,
+            BinOp::Mul => lhs * rhs,
+            BinOp::Div => lhs / rhs,
+        })),
+        _ => Err(span.replace(RuntimeError::InvalidOperands {
+            op: op.clone(),
+        })),
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    /// Makes a function item callable. Other items are ignored.
+    pub fn define(&mut self, item: Item) {
+        if let Item::Function { name, args, body, .. } = item {
+            let name = name.get_value().clone();
+            let func = Function {
+                name: name.clone(),
+                args: args.iter().map(|arg| arg.get_value().name.get_value().clone()).collect(),
+                body,
+            };
+            self.functions.insert(name, Rc::new(func));
+        }
+    }
+
+    /// Evaluates a top-level statement, whose `let` bindings stay visible
+    /// to the statements evaluated after it.
+    pub fn eval_statement(&mut self, stmt: &Span<Statement>) -> EvalResult {
+        let mut globals = mem::take(&mut self.globals);
+        let result = self.statement(&mut globals, &stmt.peek(), stmt.get_value(), 0);
+        self.globals = globals;
+        result
+    }
+
+    fn statement(&self, locals: &mut Locals, span: &Span<()>, stmt: &Statement, depth: usize) -> EvalResult {
+        match *stmt {
+            Statement::Expr(ref expr) => self.expr(locals, span, expr.get_value(), depth),
+            Statement::Let { ref name, ref value } => {
+                let value = self.expr(locals, &value.peek(), value.get_value().get_value(), depth)?;
+                locals.insert(name.get_value().clone(), value.clone());
+                Ok(value)
+            },
+            Statement::Item(Item::StrayDoc { .. }) => Ok(Value::Void),
+            Statement::Item(_) => Err(span.replace(RuntimeError::Unsupported {
+                feature: "nested items",
+            })),
+            Statement::Error(_) => Err(span.replace(RuntimeError::InvalidCode)),
+        }
+    }
+
+    fn block(&self, locals: &Locals, exprs: &[Span<Typed<Expr>>], depth: usize) -> EvalResult {
+        let mut value = Value::Void;
+        for expr in exprs {
+            value = self.expr(locals, &expr.peek(), expr.get_value().get_value(), depth)?;
+        }
+        Ok(value)
+    }
+
+    fn expr(&self, locals: &Locals, span: &Span<()>, expr: &Expr, depth: usize) -> EvalResult {
+        match *expr {
+            Expr::ConstInteger(value) => i32::try_from(value)
+                .map(Value::Integer)
+                .map_err(|_| span.replace(RuntimeError::IntegerOverflow)),
+            Expr::ConstLong(value) => Ok(Value::Long(value)),
+            Expr::ConstNumber(value) => Ok(Value::Real(value)),
+            Expr::ConstString(ref value) => Ok(Value::String(value.clone())),
+            Expr::ConstChar(value) => Ok(Value::Char(value)),
+            Expr::Interpolated(ref fragments) => {
+                let mut out = String::new();
+                for fragment in fragments {
+                    match *fragment {
+                        Fragment::Text(ref text) => out.push_str(text),
+                        Fragment::Hole(ref expr) => match self.expr(locals, &expr.peek(), expr.get_value().get_value(), depth)? {
+                            Value::String(ref value) => out.push_str(value),
+                            Value::Char(value) => out.push(value),
+                            value @ Value::Integer(_) | value @ Value::Long(_) | value @ Value::Real(_) |
+                            value @ Value::Boolean(_) =>
+                                out.push_str(&value.to_string()),
+                            _ => return Err(expr.replace(RuntimeError::InvalidCode)),
+                        },
+                    }
+                }
+                Ok(Value::String(out))
+            },
+            Expr::Variable(ref name) => {
+                if let Some(value) = locals.get(name) {
+                    Ok(value.clone())
+                }
+                else if let Some(func) = self.functions.get(name) {
+                    Ok(Value::Function(func.clone()))
+                }
+                else {
+                    Err(span.replace(RuntimeError::UndefinedVariable {
+                        name: name.clone(),
+                    }))
+                }
+            },
+            Expr::BinOp { ref op, ref lhs, ref rhs } => {
+                let lhs = self.expr(locals, &lhs.peek(), lhs.get_value().get_value(), depth)?;
+                let rhs = self.expr(locals, &rhs.peek(), rhs.get_value().get_value(), depth)?;
+                arithmetic(span, op, lhs, rhs)
+            },
+            Expr::Call { ref func, ref args } => {
+                let func = match self.expr(locals, &func.peek(), func.get_value().get_value(), depth)? {
+                    Value::Function(func) => func,
+                    _ => return Err(span.replace(RuntimeError::CallingNonFunction)),
+                };
+                let mut frame = HashMap::new();
+                for (name, arg) in func.args.iter().zip(args) {
+                    let value = self.expr(locals, &arg.peek(), arg.get_value().get_value(), depth)?;
+                    frame.insert(name.clone(), value);
+                }
+                self.call(span, &func, frame, depth + 1)
+            },
+            Expr::If { ref condition, ref branch_then, ref branch_else } => {
+                match self.expr(locals, &condition.peek(), condition.get_value().get_value(), depth)? {
+                    Value::Boolean(true) => self.block(locals, branch_then, depth),
+                    Value::Boolean(false) => self.block(locals, branch_else, depth),
+                    _ => Err(span.replace(RuntimeError::InvalidCode)),
+                }
+            },
+            Expr::Cast { ref value, ref to } => {
+                match (self.expr(locals, &value.peek(), value.get_value().get_value(), depth)?, to.get_value()) {
+                    (Value::Char(value), &Type::Integer) => Ok(Value::Integer(u32::from(value) as i32)),
+                    (Value::Integer(value), &Type::Long) => Ok(Value::Long(i64::from(value))),
+                    (Value::Long(value), &Type::Integer) => Ok(Value::Integer(value as i32)),
+                    (Value::Integer(value), &Type::Char) => u32::try_from(value).ok()
+                        .and_then(char::from_u32)
+                        .map(Value::Char)
+                        .ok_or_else(|| span.replace(RuntimeError::InvalidChar { value })),
+                    // converting to the type it already has
+                    (value, _) => Ok(value),
+                }
+            },
+            Expr::Error(_) => Err(span.replace(RuntimeError::InvalidCode)),
+        }
+    }
+
+    fn call(&self, span: &Span<()>, func: &Function, mut frame: Locals, depth: usize) -> EvalResult {
+        if depth > MAX_CALL_DEPTH {
+            return Err(span.replace(RuntimeError::StackOverflow));
+        }
+
+        let mut value = Value::Void;
+        for stmt in &func.body {
+            value = match *stmt.get_value() {
+                Statement::Let { .. } => {
+                    self.statement(&mut frame, &stmt.peek(), stmt.get_value(), depth)?;
+                    Value::Void
+                },
+                // doc comments that don't document anything can't be the
+                // result
+                Statement::Item(Item::StrayDoc { .. }) => continue,
+                _ => self.statement(&mut frame, &stmt.peek(), stmt.get_value(), depth)?,
+            };
+        }
+        Ok(value)
+    }
+}
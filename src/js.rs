@@ -0,0 +1,201 @@
+use ast::{Item, Module};
+use compiler;
+use typecheck::Type;
+use std::fmt::Write;
+
+/// Names that can't be used as JavaScript bindings, plus every name the
+/// loader declares and the globals it relies on, which a function or
+/// argument of the same name would redeclare or shadow. Functions and
+/// arguments with these names get a trailing underscore.
+const RESERVED: &[&str] = &[
+    "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default",
+    "delete", "do", "else", "enum", "export", "extends", "false", "finally", "for", "function",
+    "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "null",
+    "package", "private", "protected", "public", "return", "static", "super", "switch", "this",
+    "throw", "true", "try", "typeof", "var", "void", "while", "with", "yield", "arguments", "eval",
+    "WASM_FILE", "strings", "newString", "decoder", "imports", "instance", "load", "exports",
+    "toInt", "toLong", "toChar", "unsupported",
+    "Number", "String", "BigInt", "TextDecoder", "Uint8Array", "URL", "fetch", "Response", "WebAssembly",
+    "Error", "TypeError", "RangeError",
+];
+
+fn js_name(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    }
+    else {
+        name.to_owned()
+    }
+}
+
+fn js_type(type_info: &Type) -> &'static str {
+    match *type_info {
+        Type::Integer | Type::Real => "number",
+        Type::Long => "bigint",
+        Type::Boolean => "boolean",
+        Type::String | Type::Char => "string",
+        Type::Void => "void",
+        _ => "any",
+    }
+}
+
+/// The expression that converts a JavaScript argument into what the wasm
+/// function expects.
+fn convert_arg(name: &str, type_info: &Type) -> String {
+    match *type_info {
+        Type::Integer => format!("toInt({:?}, {})", name, name),
+        Type::Long => format!("toLong({:?}, {})", name, name),
+        Type::Real => format!("Number({})", name),
+        Type::Boolean => format!("({} ? 1 : 0)", name),
+        Type::String => format!("newString(String({}))", name),
+        Type::Char => format!("toChar({:?}, {})", name, name),
+        _ => format!("unsupported({:?})", name),
+    }
+}
+
+fn convert_result(value: &str, type_info: &Type) -> String {
+    match *type_info {
+        Type::Boolean => format!("{} !== 0", value),
+        Type::String => format!("strings[{}]", value),
+        Type::Char => format!("String.fromCodePoint({})", value),
+        _ => value.to_owned(),
+    }
+}
+
+const PRELUDE: &str = r#"
+// Strings live on this side, and the wasm module refers to them by their
+// index in this array. They're kept for as long as the module is loaded.
+const strings = [];
+
+function newString(string) {
+  strings.push(string);
+  return strings.length - 1;
+}
+
+const decoder = new TextDecoder();
+
+// Functions the wasm module imports, which match the interpreter's output.
+const imports = {
+  env: {
+    string_from_memory: (offset, len) =>
+      newString(decoder.decode(new Uint8Array(exports().memory.buffer, offset, len))),
+    concat: (lhs, rhs) => newString(strings[lhs] + strings[rhs]),
+    int_to_string: (value) => newString(String(value)),
+    long_to_string: (value) => newString(String(value)),
+    real_to_string: (value) => newString(Number.isInteger(value) ? value.toFixed(1) : String(value)),
+    bool_to_string: (value) => newString(value !== 0 ? "true" : "false"),
+    char_to_string: (value) => newString(String.fromCodePoint(value)),
+    check_char: (value) => {
+      if (value < 0 || value > 0x10ffff || (value >= 0xd800 && value <= 0xdfff)) {
+        throw new RangeError(`${value} isn't a valid character`);
+      }
+      return value;
+    },
+  },
+};
+
+let instance = null;
+
+/**
+ * Compiles and instantiates the module. Takes a URL or `Response` to fetch
+ * it from, or its bytes, and defaults to the file next to this script.
+ * Must finish before any other export is called.
+ */
+export async function load(source = new URL(WASM_FILE, import.meta.url)) {
+  let bytes = source;
+  if (typeof source === "string" || source instanceof URL) {
+    source = await fetch(source);
+  }
+  if (typeof Response !== "undefined" && source instanceof Response) {
+    bytes = await source.arrayBuffer();
+  }
+  const result = await WebAssembly.instantiate(bytes, imports);
+  instance = result.instance;
+  return instance;
+}
+
+function exports() {
+  if (instance === null) {
+    throw new Error(`${WASM_FILE} hasn't been loaded yet; await load() first`);
+  }
+  return instance.exports;
+}
+
+function toInt(name, value) {
+  if (!Number.isInteger(value) || value < -2147483648 || value > 2147483647) {
+    throw new TypeError(`${name} must be a 32-bit integer, got ${value}`);
+  }
+  return value;
+}
+
+// i64s cross into JavaScript as BigInts.
+function toLong(name, value) {
+  if (typeof value !== "bigint" && !Number.isInteger(value)) {
+    throw new TypeError(`${name} must be a 64-bit integer, got ${value}`);
+  }
+  const long = BigInt(value);
+  if (BigInt.asIntN(64, long) !== long) {
+    throw new TypeError(`${name} must be a 64-bit integer, got ${value}`);
+  }
+  return long;
+}
+
+function toChar(name, value) {
+  const chars = [...String(value)];
+  if (chars.length !== 1) {
+    throw new TypeError(`${name} must be a single character, got ${value}`);
+  }
+  return chars[0].codePointAt(0);
+}
+
+function unsupported(name) {
+  throw new TypeError(`${name} has a type that can't be passed to wasm yet`);
+}
+"#;
+
+/// Generates an ES module that loads the wasm file compiled from `module`
+/// and wraps each of its exports in a function that takes and returns
+/// JavaScript values.
+pub fn loader(module: &Module, wasm_file: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated by tifflang from `{}`. Don't edit this file by hand.", module.name).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "const WASM_FILE = {:?};", wasm_file).unwrap();
+    out.push_str(PRELUDE);
+
+    for (export, item) in compiler::exported_functions(module) {
+        let (doc, args, result) = match *item {
+            Item::Function { ref doc, ref args, ref result, .. } => (doc, args, result.get_value()),
+            _ => continue,
+        };
+        let names: Vec<String> = args.iter()
+            .map(|arg| js_name(arg.get_value().name.get_value()))
+            .collect();
+
+        writeln!(out).unwrap();
+        writeln!(out, "/**").unwrap();
+        if let Some(ref doc) = *doc {
+            // `*/` would end the comment early
+            for line in doc.get_value().replace("*/", "*\\/").lines().chain(Some("")) {
+                writeln!(out, " *{}{}", if line.is_empty() { "" } else { " " }, line).unwrap();
+            }
+        }
+        for (arg, name) in args.iter().zip(&names) {
+            writeln!(out, " * @param {{{}}} {}", js_type(arg.get_value().type_desc.get_value()), name).unwrap();
+        }
+        writeln!(out, " * @returns {{{}}}", js_type(result)).unwrap();
+        writeln!(out, " */").unwrap();
+
+        writeln!(out, "export function {}({}) {{", js_name(&export), names.join(", ")).unwrap();
+        let converted: Vec<String> = args.iter().zip(&names)
+            .map(|(arg, name)| convert_arg(name, arg.get_value().type_desc.get_value()))
+            .collect();
+        let call = format!("exports()[{:?}]({})", export, converted.join(", "));
+        match *result {
+            Type::Void => writeln!(out, "  {};", call).unwrap(),
+            ref result => writeln!(out, "  return {};", convert_result(&call, result)).unwrap(),
+        }
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
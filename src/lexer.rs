@@ -1,20 +1,103 @@
-use regex::{Regex, RegexSet};
+use std::borrow::Cow;
+use std::mem;
 use std::rc::Rc;
 use span::Span;
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
 use std::str::FromStr;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum LexError {
+    UnexpectedCharacter(char),
+    UnterminatedComment,
+    UnterminatedString,
+    UnterminatedChar,
+    /// A character literal that's empty or holds more than one character.
+    InvalidChar,
+    InvalidEscape {
+        sequence: String,
+    },
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+    },
+    MissingDigits,
+    InvalidSuffix {
+        suffix: String,
+    },
+    OutOfRange {
+        literal: String,
+        type_name: &'static str,
+    },
+}
+
+impl LexError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            LexError::UnexpectedCharacter(_) => "E0010",
+            LexError::UnterminatedComment => "E0011",
+            LexError::UnterminatedString => "E0012",
+            LexError::InvalidEscape { .. } => "E0013",
+            LexError::InvalidDigit { .. } => "E0014",
+            LexError::MissingDigits => "E0015",
+            LexError::InvalidSuffix { .. } => "E0016",
+            LexError::OutOfRange { .. } => "E0017",
+            LexError::UnterminatedChar => "E0018",
+            LexError::InvalidChar => "E0019",
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LexError::UnexpectedCharacter(ch) => write!(fmt, "unexpected character `{}`", ch.escape_default()),
+            LexError::UnterminatedComment => write!(fmt, "unterminated block comment"),
+            LexError::UnterminatedString => write!(fmt, "unterminated string"),
+            LexError::UnterminatedChar => write!(fmt, "unterminated character literal"),
+            LexError::InvalidChar => write!(fmt, "a character literal must hold exactly one character"),
+            LexError::InvalidEscape { ref sequence } => write!(fmt, "invalid escape sequence `{}`", sequence),
+            LexError::InvalidDigit { digit, radix } => write!(fmt, "invalid digit `{}` in a base {} literal", digit, radix),
+            LexError::MissingDigits => write!(fmt, "number has no digits"),
+            LexError::InvalidSuffix { ref suffix } => write!(fmt, "invalid suffix `{}` for a number", suffix),
+            LexError::OutOfRange { ref literal, type_name } =>
+                write!(fmt, "literal `{}` doesn't fit in {}", literal, type_name),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Token {
+    /// Input the lexer couldn't make sense of. The parser reports it like
+    /// any other unexpected token.
+    Error(LexError),
     Ident(String),
     Number(f64),
     Integer(i64),
+    /// An integer with an `i64` suffix.
+    Long(i64),
     String(String),
+    /// A string with expressions embedded in it, like `"a {b} c"`. Strings
+    /// without any are `Token::String`.
+    InterpolatedString(Vec<StringPart>),
+    Char(char),
+    /// A `///` comment, which documents the item after it, with the
+    /// slashes taken off.
+    Doc(String),
+    /// A `//!` comment, which documents the module it's at the top of.
+    ModuleDoc(String),
     Class,
     Struct,
     Fn,
     If,
     Else,
     Let,
+    Import,
+    As,
+    /// A line break that ends a statement. See `insert_newlines`.
+    Newline,
+    Semicolon,
     ParenLeft,
     ParenRight,
     CurlyLeft,
@@ -26,188 +109,894 @@ pub enum Token {
     Aster,
     Slash,
     Comma,
+    Dot,
     Equals,
     Eof,
 }
 
-pub struct Lexer {
-    whitespace: Regex,
-    set: RegexSet,
-    regexes: Vec<Regex>,
+/// A piece of an interpolated string.
+#[derive(Debug, Clone)]
+pub enum StringPart {
+    Text(String),
+    /// The tokens of an embedded expression, ending with a `Token::Eof` in
+    /// place of the `}` that closes it. Line breaks in it are only
+    /// whitespace.
+    Hole(Vec<Span<Token>>),
+}
+
+impl Token {
+    pub fn is_error(&self) -> bool {
+        matches!(*self, Token::Error(_))
+    }
+
+    /// Whether a statement can end with this token.
+    pub(crate) fn ends_statement(&self) -> bool {
+        matches!(
+            *self,
+            Token::Error(_) | Token::Ident(_) | Token::Number(_) | Token::Integer(_) | Token::Long(_) |
+            Token::String(_) | Token::InterpolatedString(_) | Token::Char(_) | Token::Doc(_) |
+            Token::ModuleDoc(_) | Token::ParenRight | Token::CurlyRight
+        )
+    }
+
+    pub fn is_doc(&self) -> bool {
+        matches!(*self, Token::Doc(_) | Token::ModuleDoc(_))
+    }
+
+    /// Whether a doc comment on the line before this token can belong to
+    /// it, so the line break between them doesn't end a statement.
+    fn takes_doc(&self) -> bool {
+        matches!(*self, Token::Fn | Token::Class | Token::Doc(_) | Token::ModuleDoc(_))
+    }
+
+    /// Whether this token can only continue what's before it, so a line
+    /// break in front of it never ends a statement.
+    fn continues_statement(&self) -> bool {
+        matches!(
+            *self,
+            Token::Else | Token::As | Token::CurlyLeft | Token::CurlyRight | Token::ParenRight | Token::Arrow |
+            Token::Colon | Token::Comma | Token::Equals | Token::Semicolon | Token::Eof
+        )
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            Token::Ident(ref ident) => return write!(fmt, "identifier `{}`", ident),
+            Token::Number(num) => return write!(fmt, "number `{}`", num),
+            Token::Integer(int) => return write!(fmt, "integer `{}`", int),
+            Token::Long(int) => return write!(fmt, "integer `{}i64`", int),
+            Token::String(ref string) => return write!(fmt, "string {:?}", string),
+            Token::InterpolatedString(_) => return write!(fmt, "interpolated string"),
+            Token::Char(ch) => return write!(fmt, "character {:?}", ch),
+            Token::Doc(_) => return write!(fmt, "doc comment"),
+            Token::ModuleDoc(_) => return write!(fmt, "module doc comment"),
+            Token::Eof => return write!(fmt, "end of file"),
+            Token::Error(ref err) => return write!(fmt, "{}", err),
+            Token::Class => "class",
+            Token::Struct => "struct",
+            Token::Fn => "fn",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Let => "let",
+            Token::Import => "import",
+            Token::As => "as",
+            Token::Newline => return write!(fmt, "newline"),
+            Token::Semicolon => ";",
+            Token::ParenLeft => "(",
+            Token::ParenRight => ")",
+            Token::CurlyLeft => "{",
+            Token::CurlyRight => "}",
+            Token::Colon => ":",
+            Token::Arrow => "->",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Aster => "*",
+            Token::Slash => "/",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Equals => "=",
+        };
+        write!(fmt, "`{}`", text)
+    }
 }
 
-struct Rule {
-    regex: &'static str,
-    process: fn(&[Span<&str>]) -> Token,
+/// A token, or an error with byte offsets relative to the start of the text
+/// that was being lexed.
+pub(crate) type TokenResult = Result<Token, (usize, usize, LexError)>;
+
+/// Parses a numeric literal made of digits followed by a suffix that
+/// starts at `body_end`. Integers are `int`, which is 32 bits, unless
+/// they're suffixed with `i64` or `r` (for real); `i32` is allowed too, and
+/// means the same as no suffix. Anything with a fraction or exponent is
+/// `real`.
+pub(crate) fn number(literal: &str, body_end: usize) -> TokenResult {
+    let (body, suffix) = literal.split_at(body_end);
+
+    let (radix, start) = match body.get(..2) {
+        Some("0x") => (16, 2),
+        Some("0b") => (2, 2),
+        Some("0o") => (8, 2),
+        _ => (10, 0),
+    };
+    let text = &body[start..];
+    let is_real = radix == 10 && text.contains(&['.', 'e', 'E'][..]);
+    if let Some(index) = text.find(|ch: char| ch.is_ascii_digit() && ch.to_digit(10).unwrap() >= radix) {
+        return Err((start + index, start + index + 1, LexError::InvalidDigit {
+            digit: text[index..].chars().next().unwrap(),
+            radix,
+        }));
+    }
+    let digits: String = text.chars().filter(|&ch| ch != '_').collect();
+    if digits.is_empty() {
+        return Err((0, literal.len(), LexError::MissingDigits));
+    }
+
+    let out_of_range = |type_name| (0, literal.len(), LexError::OutOfRange {
+        literal: literal.to_owned(),
+        type_name,
+    });
+    // an exponent made only of separators leaves no digits after the `e`
+    let real = || match f64::from_str(&digits) {
+        Ok(value) if value.is_finite() => Ok(Token::Number(value)),
+        Ok(_) => Err(out_of_range("real")),
+        Err(_) => Err((0, body_end, LexError::MissingDigits)),
+    };
+    let integer = |type_name| i32::from_str_radix(&digits, radix)
+        .map(|value| Token::Integer(i64::from(value)))
+        .map_err(|_| out_of_range(type_name));
+
+    match suffix {
+        "" if is_real => real(),
+        "" if !is_real => integer("int"),
+        "i32" if !is_real => integer("i32"),
+        "i64" if !is_real => i64::from_str_radix(&digits, radix)
+            .map(Token::Long)
+            .map_err(|_| out_of_range("i64")),
+        "r" if radix == 10 => real(),
+        suffix => Err((body_end, literal.len(), LexError::InvalidSuffix {
+            suffix: suffix.to_owned(),
+        })),
+    }
 }
 
-const REGEXES: &[Rule] = &[
-    Rule {
-        regex: r"^\(",
-        process: |_captures| -> Token {
-            Token::ParenLeft
+/// Decodes the escape sequences in the contents of a string literal.
+pub(crate) fn unescape(text: &str) -> Result<String, (usize, usize, LexError)> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
         }
-    },
-    Rule {
-        regex: r"^\)",
-        process: |_captures| -> Token {
-            Token::ParenRight
+        let decoded = match chars.next() {
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, '"')) => Some('"'),
+            Some((_, '\'')) => Some('\''),
+            Some((_, 'u')) if chars.peek().map(|&(_, ch)| ch) == Some('{') => {
+                let mut digits = String::new();
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, ch)) => digits.push(ch),
+                        None => {
+                            digits.clear();
+                            break;
+                        },
+                    }
+                }
+                if digits.is_empty() || digits.len() > 6 {
+                    None
+                }
+                else {
+                    u32::from_str_radix(&digits, 16).ok().and_then(::std::char::from_u32)
+                }
+            },
+            _ => None,
+        };
+        match decoded {
+            Some(ch) => out.push(ch),
+            None => {
+                let end = chars.peek().map(|&(index, _)| index).unwrap_or(text.len());
+                return Err((start, end, LexError::InvalidEscape {
+                    sequence: text[start..end].to_owned(),
+                }));
+            },
         }
-    },
-    Rule {
-        regex: r"^\{",
-        process: |_captures| -> Token {
-            Token::CurlyLeft
+    }
+    Ok(out)
+}
+
+/// Parses a character literal like `'a'` or `'\n'`, quotes included.
+pub(crate) fn char_literal(literal: &str) -> TokenResult {
+    let contents = unescape(&literal[1..literal.len() - 1])
+        .map_err(|(first, last, err)| (first + 1, last + 1, err))?;
+    let mut chars = contents.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(Token::Char(ch)),
+        _ => Err((0, literal.len(), LexError::InvalidChar)),
+    }
+}
+
+/// The length of the character literal at the start of `input`, or `None`
+/// if it isn't closed on the same line.
+fn char_len(input: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut index = 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\'' => return Some(index + 1),
+            b'\n' => return None,
+            b'\\' if bytes.get(index + 1) != Some(&b'\n') => index += 2,
+            _ => index += 1,
         }
-    },
-    Rule {
-        regex: r"^\}",
-        process: |_captures| -> Token {
-            Token::CurlyRight
+    }
+    None
+}
+
+/// Matches a `///` or `//!` doc comment at the start of `input`, giving
+/// its length and the text after the slashes, less one space. Comments
+/// starting with four or more slashes are ordinary comments.
+pub(crate) fn doc_comment(input: &str) -> Option<(usize, Token)> {
+    let doc: fn(String) -> Token = if input.starts_with("///") && !input.starts_with("////") {
+        Token::Doc
+    }
+    else if input.starts_with("//!") {
+        Token::ModuleDoc
+    }
+    else {
+        return None;
+    };
+    let len = input.find('\n').unwrap_or(input.len());
+    let text = input[3..len].trim_end_matches('\r');
+    Some((len, doc(text.strip_prefix(' ').unwrap_or(text).to_owned())))
+}
+
+/// Matches a raw string like `r"..."` or `r#"..."#` at the start of
+/// `input`. Gives its length and contents, or the length of its opening
+/// delimiter if it's never closed.
+pub(crate) fn raw_string(input: &str) -> Option<Result<(usize, &str), usize>> {
+    if !input.starts_with('r') {
+        return None;
+    }
+    let hashes = input[1..].bytes().take_while(|&byte| byte == b'#').count();
+    if input.as_bytes().get(1 + hashes) != Some(&b'"') {
+        return None;
+    }
+    let open = hashes + 2;
+    let close = format!("\"{}", "#".repeat(hashes));
+    match input[open..].find(&close) {
+        Some(len) => Some(Ok((open + len + close.len(), &input[open..open + len]))),
+        None => Some(Err(open)),
+    }
+}
+
+/// The length of the block comment at the start of `input`, including any
+/// nested comments, or `None` if it never ends.
+pub(crate) fn block_comment(input: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut depth = 0;
+    let mut index = 0;
+    while index + 1 < bytes.len() {
+        match (bytes[index], bytes[index + 1]) {
+            (b'/', b'*') => {
+                depth += 1;
+                index += 2;
+            },
+            (b'*', b'/') => {
+                depth -= 1;
+                index += 2;
+                if depth == 0 {
+                    return Some(index);
+                }
+            },
+            _ => index += 1,
         }
-    },
-    Rule {
-        regex: r"^:",
-        process: |_captures| -> Token {
-            Token::Colon
+    }
+    None
+}
+
+fn count(bytes: &[u8], from: usize, matches: fn(&u8) -> bool) -> usize {
+    bytes.get(from..).map(|bytes| bytes.iter().take_while(|&byte| matches(byte)).count()).unwrap_or(0)
+}
+
+fn is_digit_or_separator(byte: &u8) -> bool {
+    byte.is_ascii_digit() || *byte == b'_'
+}
+
+fn is_hex_digit_or_separator(byte: &u8) -> bool {
+    byte.is_ascii_hexdigit() || *byte == b'_'
+}
+
+fn is_ident_char(byte: &u8) -> bool {
+    byte.is_ascii_alphanumeric() || *byte == b'_'
+}
+
+/// The length of the identifier at the start of `input`. Identifiers are
+/// made of Unicode XID characters, with `_` also allowed at the start.
+fn ident_len(input: &str) -> usize {
+    let ascii = count(input.as_bytes(), 0, is_ident_char);
+    if input.as_bytes().get(ascii).is_none_or(u8::is_ascii) {
+        return ascii;
+    }
+    input[ascii..].char_indices()
+        .find(|&(_, ch)| !UnicodeXID::is_xid_continue(ch))
+        .map(|(index, _)| ascii + index)
+        .unwrap_or(input.len())
+}
+
+/// Puts an identifier in NFC, so that it's the same however its
+/// characters were encoded.
+pub(crate) fn normalize<'a>(ident: &'a str) -> Cow<'a, str> {
+    if ident.is_ascii() {
+        Cow::Borrowed(ident)
+    }
+    else {
+        Cow::Owned(ident.nfc().collect())
+    }
+}
+
+/// Finds where the digits of the number at the start of `input` end, and
+/// where its suffix ends.
+fn number_len(input: &str) -> (usize, usize) {
+    let bytes = input.as_bytes();
+    // a prefix without digits is still a prefix, so the missing digits can
+    // be reported
+    let body = match (bytes[0], bytes.get(1)) {
+        (b'0', Some(&b'x')) => 2 + count(bytes, 2, is_hex_digit_or_separator),
+        (b'0', Some(&b'b')) | (b'0', Some(&b'o')) => 2 + count(bytes, 2, is_digit_or_separator),
+        _ => {
+            let mut end = 1 + count(bytes, 1, is_digit_or_separator);
+            if bytes.get(end) == Some(&b'.') && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) {
+                end += 2 + count(bytes, end + 2, is_digit_or_separator);
+            }
+            if let Some(&b'e') | Some(&b'E') = bytes.get(end) {
+                let sign = matches!(bytes.get(end + 1), Some(&b'+') | Some(&b'-')) as usize;
+                let digits = count(bytes, end + 1 + sign, is_digit_or_separator);
+                if digits > 0 {
+                    end += 1 + sign + digits;
+                }
+            }
+            end
+        },
+    };
+    let suffix = if bytes.get(body).is_some_and(u8::is_ascii_alphabetic) {
+        1 + count(bytes, body + 1, is_ident_char)
+    }
+    else {
+        0
+    };
+    (body, body + suffix)
+}
+
+/// Lexes the token at the start of `input`, which mustn't be empty or
+/// start with whitespace, a comment that's closed (doc comments are fine)
+/// or a quoted string (which `Scanner::string` handles, since it can have
+/// tokens inside it). Gives the number of bytes consumed, which only differs
+/// from the token's length when it's an unterminated string or comment that
+/// swallows the rest of the input. An unterminated character literal
+/// swallows the rest of its line.
+fn scan(input: &str) -> (usize, TokenResult) {
+    let bytes = input.as_bytes();
+    let punctuation = match bytes[0] {
+        b'(' => Some(Token::ParenLeft),
+        b')' => Some(Token::ParenRight),
+        b'{' => Some(Token::CurlyLeft),
+        b'}' => Some(Token::CurlyRight),
+        b':' => Some(Token::Colon),
+        b'-' if bytes.get(1) == Some(&b'>') => return (2, Ok(Token::Arrow)),
+        b'+' => Some(Token::Plus),
+        b'-' => Some(Token::Minus),
+        b'*' => Some(Token::Aster),
+        b'/' if bytes.get(1) == Some(&b'*') => return (input.len(), Err((0, 2, LexError::UnterminatedComment))),
+        b'/' if bytes.get(1) == Some(&b'/') => {
+            let (len, doc) = doc_comment(input).unwrap();
+            return (len, Ok(doc));
+        },
+        b'/' => Some(Token::Slash),
+        b',' => Some(Token::Comma),
+        b';' => Some(Token::Semicolon),
+        b'.' => Some(Token::Dot),
+        b'=' => Some(Token::Equals),
+        _ => None,
+    };
+    if let Some(token) = punctuation {
+        return (1, Ok(token));
+    }
+
+    match raw_string(input) {
+        Some(Ok((len, contents))) => return (len, Ok(Token::String(contents.to_owned()))),
+        Some(Err(open)) => return (input.len(), Err((0, open, LexError::UnterminatedString))),
+        None => (),
+    }
+
+    let first = input.chars().next().unwrap();
+    if first == '_' || UnicodeXID::is_xid_start(first) {
+        let len = ident_len(input);
+        return (len, Ok(match &*normalize(&input[..len]) {
+            "class" => Token::Class,
+            "struct" => Token::Struct,
+            "fn" => Token::Fn,
+            "if" => Token::If,
+            "else" => Token::Else,
+            "let" => Token::Let,
+            "import" => Token::Import,
+            "as" => Token::As,
+            ident => Token::Ident(ident.to_owned()),
+        }));
+    }
+
+    match bytes[0] {
+        b'0'..=b'9' => {
+            let (body, len) = number_len(input);
+            (len, number(&input[..len], body))
+        },
+        b'\'' => match char_len(input) {
+            Some(len) => (len, char_literal(&input[..len])),
+            None => (input.find('\n').unwrap_or(input.len()), Err((0, 1, LexError::UnterminatedChar))),
+        },
+        _ => {
+            let ch = input.chars().next().unwrap();
+            (ch.len_utf8(), Err((0, ch.len_utf8(), LexError::UnexpectedCharacter(ch))))
+        },
+    }
+}
+
+/// Text between tokens that doesn't mean anything to the parser. Doc
+/// comments are tokens rather than trivia.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Trivia {
+    /// Spaces and tabs, and any other whitespace except line breaks.
+    Whitespace,
+    /// A `\n` or `\r\n`.
+    Newline,
+    LineComment,
+    BlockComment,
+    /// Input consumed by an error token without being part of its span,
+    /// like the rest of the file after an unterminated string.
+    Skipped,
+}
+
+/// The kind and length of the trivia at the start of `input`, if it starts
+/// with any.
+fn trivia(input: &str) -> Option<(Trivia, usize)> {
+    let bytes = input.as_bytes();
+    match (*bytes.first()?, bytes.get(1)) {
+        (b'\n', _) => Some((Trivia::Newline, 1)),
+        (b'\r', Some(&b'\n')) => Some((Trivia::Newline, 2)),
+        (b'/', Some(&b'/')) if doc_comment(input).is_none() =>
+            Some((Trivia::LineComment, input.find('\n').unwrap_or(input.len()))),
+        (b'/', Some(&b'*')) => block_comment(input).map(|len| (Trivia::BlockComment, len)),
+        _ => {
+            let len = input.char_indices()
+                .find(|&(index, ch)| !ch.is_whitespace() || ch == '\n' || input[index..].starts_with("\r\n"))
+                .map(|(index, _)| index)
+                .unwrap_or(input.len());
+            if len > 0 {
+                Some((Trivia::Whitespace, len))
+            }
+            else {
+                None
+            }
+        },
+    }
+}
+
+/// Produces tokens one at a time straight from the characters of the
+/// input, without newline tokens.
+pub(crate) struct Scanner {
+    input: Rc<String>,
+    index: usize,
+    done: bool,
+}
+
+impl Scanner {
+    /// Starts lexing at a byte offset, which has to be the start of a
+    /// token or of the trivia before one.
+    pub(crate) fn new(input: Rc<String>, index: usize) -> Scanner {
+        Scanner {
+            input,
+            index,
+            done: false,
         }
-    },
-    Rule {
-        regex: r"^\->",
-        process: |_captures| -> Token {
-            Token::Arrow
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.index..]
+    }
+
+    /// Moves past `len` bytes, giving their span.
+    fn advance<T>(&mut self, value: T, len: usize) -> Span<T> {
+        let start = self.index;
+        self.index += len;
+        Span::new(value, start, self.index, self.input.clone())
+    }
+
+    /// Moves past whitespace and comments.
+    fn skip_trivia(&mut self) {
+        while let Some((_, len)) = trivia(self.rest()) {
+            self.index += len;
         }
-    },
-    Rule {
-        regex: r"^\+",
-        process: |_captures| -> Token {
-            Token::Plus
+    }
+
+    /// Lexes the token at the current position, which has to be past any
+    /// trivia. Returns `Token::Eof` at the end of the input, and `None` after
+    /// that.
+    fn token(&mut self) -> Option<Span<Token>> {
+        if self.done {
+            return None;
         }
-    },
-    Rule {
-        regex: r"^\-",
-        process: |_captures| -> Token {
-            Token::Minus
+        let start = self.index;
+        if start >= self.input.len() {
+            self.done = true;
+            return Some(Span::new(Token::Eof, start, start, self.input.clone()));
         }
-    },
-    Rule {
-        regex: r"^\*",
-        process: |_captures| -> Token {
-            Token::Aster
+        if self.rest().starts_with('"') {
+            return Some(self.string());
         }
-    },
-    Rule {
-        regex: r"^/",
-        process: |_captures| -> Token {
-            Token::Slash
+        let (consumed, result) = scan(self.rest());
+        self.index += consumed;
+        Some(match result {
+            Ok(token) => Span::new(token, start, start + consumed, self.input.clone()),
+            Err((first, last, err)) => Span::new(Token::Error(err), start + first, start + last, self.input.clone()),
+        })
+    }
+
+    /// Lexes the quoted string at the current position. Text in braces is an
+    /// embedded expression, and `{{` and `}}` stand for the braces
+    /// themselves.
+    fn string(&mut self) -> Span<Token> {
+        let start = self.index;
+        let input = self.input.clone();
+        let bytes = input.as_bytes();
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut error = None;
+        let mut index = start + 1;
+        let mut text_start = index;
+        loop {
+            let byte = match bytes.get(index) {
+                Some(&byte) => byte,
+                None => {
+                    self.index = input.len();
+                    return Span::new(Token::Error(LexError::UnterminatedString), start, start + 1, input.clone());
+                },
+            };
+            if let b'"' | b'{' | b'}' = byte {
+                match unescape(&input[text_start..index]) {
+                    Ok(unescaped) => text.push_str(&unescaped),
+                    Err((first, last, err)) => if error.is_none() {
+                        error = Some((text_start + first, text_start + last, err));
+                    },
+                }
+            }
+            match byte {
+                b'"' => break,
+                // the braces of a `\u{...}` escape don't start a hole
+                b'\\' if input[index + 1..].starts_with("u{") => {
+                    index += 3;
+                    while let Some(&byte) = bytes.get(index) {
+                        if byte == b'"' {
+                            break;
+                        }
+                        index += 1;
+                        if byte == b'}' {
+                            break;
+                        }
+                    }
+                },
+                b'\\' => index += 2,
+                b'{' if bytes.get(index + 1) != Some(&b'{') => {
+                    match self.hole(index + 1) {
+                        Some((tokens, end)) => {
+                            if !text.is_empty() {
+                                parts.push(StringPart::Text(mem::take(&mut text)));
+                            }
+                            parts.push(StringPart::Hole(tokens));
+                            index = end;
+                        },
+                        None => {
+                            self.index = input.len();
+                            return Span::new(Token::Error(LexError::UnterminatedString), start, start + 1, input.clone());
+                        },
+                    }
+                    text_start = index;
+                },
+                b'{' | b'}' => {
+                    text.push(byte as char);
+                    index += if bytes.get(index + 1) == Some(&byte) { 2 } else { 1 };
+                    text_start = index;
+                },
+                _ => index += 1,
+            }
         }
-    },
-    Rule {
-        regex: r"^,",
-        process: |_captures| -> Token {
-            Token::Comma
+
+        self.index = index + 1;
+        if let Some((first, last, err)) = error {
+            return Span::new(Token::Error(err), first, last, input.clone());
         }
-    },
-    Rule {
-        regex: r"^=",
-        process: |_captures| -> Token {
-            Token::Equals
+        let token = if parts.is_empty() {
+            Token::String(text)
         }
-    },
+        else {
+            if !text.is_empty() {
+                parts.push(StringPart::Text(text));
+            }
+            Token::InterpolatedString(parts)
+        };
+        Span::new(token, start, self.index, input.clone())
+    }
 
-    Rule {
-        regex: r"^([a-zA-Z_][a-zA-Z0-9_]*)",
-        process: |captures| -> Token {
-            let ident = captures[1].get_value().to_owned();
-            match ident {
-                "class" => Token::Class,
-                "struct" => Token::Struct,
-                "fn" => Token::Fn,
-                "if" => Token::If,
-                "else" => Token::Else,
-                "let" => Token::Let,
-                ident => Token::Ident(ident.to_owned()),
+    /// Lexes the expression in an interpolated string that starts at
+    /// `index`, up to the `}` that closes it. Gives the tokens and where the
+    /// string continues, or `None` if the input ends first.
+    fn hole(&self, index: usize) -> Option<(Vec<Span<Token>>, usize)> {
+        let mut scanner = Scanner::new(self.input.clone(), index);
+        let mut tokens = vec![];
+        let mut depth = 0;
+        loop {
+            let token = scanner.next()?;
+            match *token.get_value() {
+                Token::CurlyRight if depth == 0 => {
+                    let end = token.range().1;
+                    tokens.push(token.replace(Token::Eof));
+                    return Some((tokens, end));
+                },
+                Token::CurlyLeft => depth += 1,
+                Token::CurlyRight => depth -= 1,
+                Token::Eof => return None,
+                _ => (),
             }
+            tokens.push(token);
         }
-    },
-    Rule {
-        regex: r"^([\-\+]?[0-9]+\.[0-9]+(?:e[0-9]+(?:\.[0-9]+)?)?)",
-        process: |captures| -> Token {
-            Token::Number(f64::from_str(captures[1].get_value()).unwrap())
-        }
-    },
-    Rule {
-        regex: r"^([\-\+]?[0-9]+)",
-        process: |captures| -> Token {
-            Token::Integer(i64::from_str(captures[1].get_value()).unwrap())
+    }
+}
+
+impl Iterator for Scanner {
+    type Item = Span<Token>;
+
+    fn next(&mut self) -> Option<Span<Token>> {
+        self.skip_trivia();
+        self.token()
+    }
+}
+
+/// A token along with the trivia around it. A token's trailing trivia runs
+/// up to the end of its line, and the line break belongs to the next
+/// token's leading trivia.
+#[derive(Debug, Clone)]
+pub struct LosslessToken {
+    pub leading: Vec<Span<Trivia>>,
+    pub token: Span<Token>,
+    pub trailing: Vec<Span<Trivia>>,
+}
+
+impl fmt::Display for LosslessToken {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for trivia in &self.leading {
+            fmt.write_str(trivia.text())?;
         }
-    },
-    Rule {
-        regex: r#"^"([^"]*)""#,
-        process: |captures| -> Token {
-            Token::String(captures[1].get_value().to_owned().to_owned())
+        fmt.write_str(self.token.text())?;
+        for trivia in &self.trailing {
+            fmt.write_str(trivia.text())?;
         }
+        Ok(())
     }
-];
+}
 
-impl Lexer {
-    pub fn new() -> Lexer {
-        Lexer {
-            whitespace: Regex::new(r"^\s+").unwrap(),
-            set: RegexSet::new(REGEXES.iter().map(|rule| rule.regex)).unwrap(),
-            regexes: REGEXES.iter().map(|rule| Regex::new(rule.regex).unwrap()).collect(),
+/// Tokens that keep every byte of the source, so writing them all out in
+/// order gives back the input exactly. There are no `Token::Newline`s,
+/// since the line breaks they stand for are in the trivia.
+pub struct LosslessTokens {
+    scanner: Scanner,
+}
+
+impl Iterator for LosslessTokens {
+    type Item = LosslessToken;
+
+    fn next(&mut self) -> Option<LosslessToken> {
+        let mut leading = vec![];
+        while let Some((kind, len)) = trivia(self.scanner.rest()) {
+            leading.push(self.scanner.advance(kind, len));
         }
-    }
 
-    pub fn lex(&self, input: Rc<String>) -> Vec<Span<Token>> {
-        let input_str = &input[..];
-        let mut index = 0;
-        let mut tokens = vec![];
-        loop {
-            if index >= input_str.len() {
+        let start = self.scanner.index;
+        let token = self.scanner.token()?;
+        let (first, last) = token.range();
+        let input = &self.scanner.input;
+        if start < first {
+            leading.push(Span::new(Trivia::Skipped, start, first, input.clone()));
+        }
+        let mut trailing = vec![];
+        if last < self.scanner.index {
+            trailing.push(Span::new(Trivia::Skipped, last, self.scanner.index, input.clone()));
+        }
+        while let Some((kind, len)) = trivia(self.scanner.rest()) {
+            if kind == Trivia::Newline {
                 break;
             }
-            if let Some(capture) = self.whitespace.find(&input_str[index..]) {
-                index += capture.end();
-                continue;
-            }
-            let matches = self.set.matches(&input_str[index..]);
-            let mut any_match = false;
-            for i in 0..REGEXES.len() {
-                if matches.matched(i) {
-                    let captures = self.regexes[i].captures(&input_str[index..]).unwrap();
-                    let first_capture = captures.get(0).unwrap();
-                    let captures: Vec<Span<&str>> = captures.iter().map(|capture| {
-                        let capture = capture.unwrap();
-                        Span::new(
-                            capture.as_str(),
-                            index + capture.start(),
-                            index + capture.end(),
-                            input.clone()
-                        )
-                    }).collect();
-                    let first = index + first_capture.start();
-                    let last = index + first_capture.end();
-                    index = last;
-                    tokens.push(Span::new(
-                        (REGEXES[i].process)(&captures[..]),
-                        first, last,
-                        input.clone()
-                    ));
-                    any_match = true;
-                    break;
+            trailing.push(self.scanner.advance(kind, len));
+        }
+
+        Some(LosslessToken {
+            leading,
+            token,
+            trailing,
+        })
+    }
+}
+
+/// Adds the errors in a token to `out`: the token itself if it's an error,
+/// or any in the holes of an interpolated string.
+pub(crate) fn collect_errors(out: &mut Vec<Span<LexError>>, token: &Span<Token>) {
+    match *token.get_value() {
+        Token::Error(ref err) => out.push(token.replace(err.clone())),
+        Token::InterpolatedString(ref parts) => for part in parts {
+            if let StringPart::Hole(ref tokens) = *part {
+                for token in tokens {
+                    collect_errors(out, token);
                 }
             }
-            if !any_match {
-                break;
-            }
+        },
+        _ => (),
+    }
+}
+
+fn track_bracket(brackets: &mut Vec<bool>, token: &Token) {
+    match *token {
+        Token::ParenLeft => brackets.push(true),
+        Token::CurlyLeft => brackets.push(false),
+        Token::ParenRight | Token::CurlyRight => {
+            brackets.pop();
+        },
+        _ => (),
+    }
+}
+
+/// The brackets left open after a list of tokens, innermost last, with
+/// `true` for parentheses. Newlines are placed differently inside them.
+pub(crate) fn open_brackets(tokens: &[Span<Token>]) -> Vec<bool> {
+    let mut brackets = vec![];
+    for token in tokens {
+        track_bracket(&mut brackets, token.get_value());
+    }
+    brackets
+}
+
+/// Adds newline tokens to a stream of tokens. Line breaks end statements,
+/// unless they're inside parentheses, or the line before can't be the end
+/// of a statement (like `a +`), or the next line can't be the start of one
+/// (like `else`), or it's between a doc comment and what it documents.
+/// Everywhere else they're just whitespace.
+pub(crate) struct Newlines<I> {
+    tokens: I,
+    input: Rc<String>,
+    /// A token held back while the newline in front of it is returned.
+    pending: Option<Span<Token>>,
+    /// Where the last token ended, if it can end a statement.
+    statement_end: Option<usize>,
+    /// Whether each open bracket is a parenthesis.
+    brackets: Vec<bool>,
+    /// Whether the last token was a doc comment.
+    after_doc: bool,
+}
+
+impl<I: Iterator<Item=Span<Token>>> Newlines<I> {
+    pub(crate) fn new(input: Rc<String>, tokens: I) -> Newlines<I> {
+        Newlines {
+            tokens,
+            input,
+            pending: None,
+            statement_end: None,
+            brackets: vec![],
+            after_doc: false,
+        }
+    }
+
+    /// Continues adding newlines to a stream of tokens that comes after
+    /// `before`.
+    pub(crate) fn resume(input: Rc<String>, tokens: I, before: &[Span<Token>]) -> Newlines<I> {
+        let mut newlines = Newlines::new(input, tokens);
+        for token in before {
+            newlines.track(token);
+        }
+        newlines
+    }
+
+    /// Updates the state used to place newlines after returning a token.
+    fn track(&mut self, token: &Span<Token>) {
+        track_bracket(&mut self.brackets, token.get_value());
+        self.statement_end = if token.get_value().ends_statement() {
+            Some(token.range().1)
+        }
+        else {
+            None
+        };
+        self.after_doc = token.get_value().is_doc();
+    }
+
+    /// Where to put a newline in front of a token, if anywhere.
+    fn newline_before(&self, token: &Span<Token>) -> Option<usize> {
+        let gap_start = self.statement_end?;
+        if self.brackets.last() == Some(&true) || token.get_value().continues_statement() ||
+            (self.after_doc && token.get_value().takes_doc())
+        {
+            return None;
+        }
+        let gap_end = token.range().0;
+        self.input.get(gap_start..gap_end)
+            .and_then(|gap| gap.find('\n'))
+            .map(|offset| gap_start + offset)
+    }
+}
+
+impl<I: Iterator<Item=Span<Token>>> Iterator for Newlines<I> {
+    type Item = Span<Token>;
+
+    fn next(&mut self) -> Option<Span<Token>> {
+        let token = match self.pending.take() {
+            Some(token) => token,
+            None => {
+                let token = self.tokens.next()?;
+                if let Some(offset) = self.newline_before(&token) {
+                    self.pending = Some(token);
+                    return Some(Span::new(Token::Newline, offset, offset + 1, self.input.clone()));
+                }
+                token
+            },
+        };
+
+        self.track(&token);
+        Some(token)
+    }
+}
+
+/// The tokens of a source, produced as they're needed. Always ends with
+/// `Token::Eof`.
+pub struct Tokens {
+    inner: Newlines<Scanner>,
+}
+
+impl Iterator for Tokens {
+    type Item = Span<Token>;
+
+    fn next(&mut self) -> Option<Span<Token>> {
+        self.inner.next()
+    }
+}
+
+pub struct Lexer;
+
+impl Default for Lexer {
+    fn default() -> Lexer {
+        Lexer::new()
+    }
+}
+
+impl Lexer {
+    pub fn new() -> Lexer {
+        Lexer
+    }
+
+    pub fn tokens(&self, input: Rc<String>) -> Tokens {
+        Tokens {
+            inner: Newlines::new(input.clone(), Scanner::new(input, 0)),
         }
-        tokens.push(Span::new(
-            Token::Eof,
-            input.len(),
-            input.len(),
-            input.clone()
-        ));
-        tokens
+    }
+
+    /// Lexes without throwing anything away, for tools that need to
+    /// reproduce the source.
+    pub fn lossless_tokens(&self, input: Rc<String>) -> LosslessTokens {
+        LosslessTokens {
+            scanner: Scanner::new(input, 0),
+        }
+    }
+
+    pub fn lex(&self, input: Rc<String>) -> Vec<Span<Token>> {
+        self.tokens(input).collect()
     }
 }
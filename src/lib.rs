@@ -0,0 +1,29 @@
+extern crate web_assembler as wasm;
+extern crate wasmi;
+extern crate unicode_xid;
+extern crate unicode_normalization;
+extern crate unicode_script;
+extern crate unicode_security;
+
+pub mod span;
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod incremental;
+pub mod resolver;
+pub mod typecheck;
+pub mod compiler;
+pub mod wat;
+pub mod js;
+pub mod diagnostic;
+pub mod lint;
+pub mod interpreter;
+pub mod runner;
+#[doc(hidden)]
+pub mod bench_support;
+mod session;
+mod repl;
+
+pub use diagnostic::{Diagnostic, Severity};
+pub use session::Session;
+pub use repl::{Repl, Evaluated};
@@ -0,0 +1,217 @@
+use ast::{Expr, Fragment, Item, Module, Program, Statement};
+use diagnostic::Diagnostic;
+use span::Span;
+use typecheck::Typed;
+use unicode_script::{Script, UnicodeScript};
+use unicode_security::MixedScript;
+use unicode_security::confusable_detection::skeleton;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Lint {
+    /// An identifier with letters from scripts that aren't written
+    /// together, which is usually a lookalike letter typed by mistake.
+    MixedScript {
+        ident: String,
+        scripts: Vec<&'static str>,
+    },
+    /// Two different identifiers that can look the same.
+    Confusable {
+        ident: String,
+        other: String,
+    },
+    /// A doc comment that doesn't document anything, or a `//!` comment
+    /// that isn't at the top of its module.
+    StrayDoc {
+        module: bool,
+    },
+}
+
+impl Lint {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Lint::MixedScript { .. } => "W0701",
+            Lint::Confusable { .. } => "W0702",
+            Lint::StrayDoc { .. } => "W0703",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Lint::MixedScript { ref ident, ref scripts } =>
+                write!(fmt, "identifier `{}` mixes {} characters", ident, scripts.join(" and ")),
+            Lint::Confusable { ref ident, ref other } =>
+                write!(fmt, "identifier `{}` can be confused with `{}`", ident, other),
+            Lint::StrayDoc { module: false } => write!(fmt, "doc comment isn't followed by an item"),
+            Lint::StrayDoc { module: true } => write!(fmt, "module doc comment isn't at the top of the module"),
+        }
+    }
+}
+
+type Idents<'a> = Vec<(&'a str, Span<()>)>;
+
+fn expr_idents<'a>(out: &mut Idents<'a>, span: &Span<()>, expr: &'a Typed<Expr>) {
+    match expr.value {
+        Expr::Variable(ref name) => for segment in name.split('.') {
+            out.push((segment, span.clone()));
+        },
+        Expr::BinOp { ref lhs, ref rhs, .. } => {
+            expr_idents(out, &lhs.peek(), lhs.get_value());
+            expr_idents(out, &rhs.peek(), rhs.get_value());
+        },
+        Expr::Call { ref func, ref args } => {
+            expr_idents(out, &func.peek(), func.get_value());
+            for arg in args {
+                expr_idents(out, &arg.peek(), arg.get_value());
+            }
+        },
+        Expr::If { ref condition, ref branch_then, ref branch_else } => {
+            expr_idents(out, &condition.peek(), condition.get_value());
+            for expr in branch_then.iter().chain(branch_else) {
+                expr_idents(out, &expr.peek(), expr.get_value());
+            }
+        },
+        Expr::Cast { ref value, .. } => expr_idents(out, &value.peek(), value.get_value()),
+        Expr::Interpolated(ref fragments) => for fragment in fragments {
+            if let Fragment::Hole(ref expr) = *fragment {
+                expr_idents(out, &expr.peek(), expr.get_value());
+            }
+        },
+        Expr::Error(_) | Expr::ConstInteger(_) | Expr::ConstLong(_) | Expr::ConstNumber(_) | Expr::ConstString(_) | Expr::ConstChar(_) => (),
+    }
+}
+
+fn statement_idents<'a>(out: &mut Idents<'a>, span: &Span<()>, stmt: &'a Statement) {
+    match *stmt {
+        Statement::Expr(ref expr) => expr_idents(out, span, expr),
+        Statement::Let { ref name, ref value } => {
+            out.push((name.get_value(), name.peek()));
+            expr_idents(out, &value.peek(), value.get_value());
+        },
+        Statement::Item(ref item) => item_idents(out, item),
+        Statement::Error(_) => (),
+    }
+}
+
+fn item_idents<'a>(out: &mut Idents<'a>, item: &'a Item) {
+    match *item {
+        Item::Function { ref name, ref args, ref body, .. } => {
+            out.push((name.get_value(), name.peek()));
+            for arg in args {
+                let name = &arg.get_value().name;
+                out.push((name.get_value(), name.peek()));
+            }
+            for stmt in body {
+                statement_idents(out, &stmt.peek(), stmt.get_value());
+            }
+        },
+        Item::Class { ref name, ref members, .. } => {
+            out.push((name.get_value(), name.peek()));
+            for member in members {
+                item_idents(out, member.get_value());
+            }
+        },
+        Item::Import { ref path, ref names } => {
+            for name in path.iter().chain(names.iter().flatten()) {
+                out.push((name.get_value(), name.peek()));
+            }
+        },
+        Item::Error(_) | Item::StrayDoc { .. } => (),
+    }
+}
+
+/// Warns about the doc comments in an item that don't document anything.
+fn stray_docs(out: &mut Vec<Diagnostic>, span: &Span<()>, item: &Item) {
+    match *item {
+        Item::StrayDoc { module } => {
+            let lint = Lint::StrayDoc { module };
+            out.push(Diagnostic::warning(lint.code(), Some(span.clone()), lint));
+        },
+        Item::Function { ref body, .. } => for stmt in body {
+            if let Statement::Item(ref item) = *stmt.get_value() {
+                stray_docs(out, &stmt.peek(), item);
+            }
+        },
+        Item::Class { ref members, .. } => for member in members {
+            stray_docs(out, &member.peek(), member.get_value());
+        },
+        _ => (),
+    }
+}
+
+/// The scripts an identifier's letters come from, in the order they first
+/// appear.
+fn scripts(ident: &str) -> Vec<&'static str> {
+    let mut out = vec![];
+    for ch in ident.chars() {
+        match ch.script() {
+            Script::Common | Script::Inherited | Script::Unknown => (),
+            script if !out.contains(&script.full_name()) => out.push(script.full_name()),
+            _ => (),
+        }
+    }
+    out
+}
+
+/// Warns about doc comments that don't document anything, and about
+/// identifiers that mix scripts or look like another identifier in the
+/// same module. Each identifier is reported where it first appears.
+/// Identifiers that are all ASCII are never confused with each other, so
+/// modules without any other kind aren't checked for that.
+pub fn lint_module(module: &Module) -> Vec<Diagnostic> {
+    let mut out = vec![];
+    let mut idents = vec![];
+    for item in &module.items {
+        stray_docs(&mut out, &item.peek(), item.get_value());
+        item_idents(&mut idents, item.get_value());
+    }
+    if idents.iter().all(|&(ident, _)| ident.is_ascii()) {
+        return out;
+    }
+
+    let mut seen = HashSet::new();
+    let mut skeletons: HashMap<String, &str> = HashMap::new();
+    for (ident, span) in idents {
+        if !seen.insert(ident) {
+            continue;
+        }
+        let mut lints = vec![];
+        if !ident.is_single_script() {
+            lints.push(Lint::MixedScript {
+                ident: ident.to_owned(),
+                scripts: scripts(ident),
+            });
+        }
+        let key: String = skeleton(ident).collect();
+        match skeletons.get(&key) {
+            Some(&other) if !(ident.is_ascii() && other.is_ascii()) => lints.push(Lint::Confusable {
+                ident: ident.to_owned(),
+                other: other.to_owned(),
+            }),
+            Some(_) => (),
+            None => {
+                skeletons.insert(key, ident);
+            },
+        }
+        for lint in lints {
+            out.push(Diagnostic::warning(lint.code(), Some(span.clone()), lint));
+        }
+    }
+    out
+}
+
+/// Lints every module in a program, pointing the warnings in imported
+/// modules at the files they came from.
+pub fn lint_program(program: &Program) -> Vec<Diagnostic> {
+    let mut out = vec![];
+    for module in &program.modules {
+        out.extend(lint_module(module).into_iter().map(|diagnostic| match program.files.get(&module.name) {
+            Some(file) => diagnostic.in_file(file),
+            None => diagnostic,
+        }));
+    }
+    out
+}
@@ -1,62 +1,491 @@
-extern crate web_assembler as wasm;
-extern crate regex;
-
-mod span;
-mod lexer;
-mod ast;
-mod parser;
-mod typecheck;
-mod compiler;
-
-use lexer::Token;
-use span::Span;
+extern crate tifflang;
+
+use tifflang::{Session, Diagnostic, Repl, wat, js};
+use tifflang::diagnostic::{self, IO_ERROR};
 use std::rc::Rc;
-use std::fs::File;
-use wasm::Dump;
-use std::io::Write;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const USAGE: &str = "\
+Usage: tifflang [options] <file>...
+       tifflang watch [options] <file>...
+       tifflang repl
+       tifflang run [--export <name>] <file> [args]...
+
+Compiles each input file to WebAssembly. Use `-` to read from stdin.
+`watch` recompiles whenever one of the files changes. `repl` evaluates
+statements and items interactively. `run` compiles a file and calls one of
+its exported functions with the given arguments, printing the result.
+
+Options:
+    -o <path>        Write the output to <path> (only valid with a single input)
+    --emit <stage>   Stop after <stage> and print its output. One of
+                     tokens, ast, typed, wat or wasm (the default)
+    --js             Also write an ES module next to the wasm output that
+                     loads it and wraps each exported function
+    --root <dir>     Look up imported modules in <dir> instead of the
+                     directory of the input file
+    --export <name>  The function for `run` to call (default: main)
+    --error-format <format>
+                     Print diagnostics as `human` (the default) or `json`,
+                     one object per line
+    -h, --help       Print this message
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    Tokens,
+    Ast,
+    Typed,
+    Wat,
+    Wasm,
+}
+
+impl Emit {
+    fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "typed" => Some(Emit::Typed),
+            "wat" => Some(Emit::Wat),
+            "wasm" => Some(Emit::Wasm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Build,
+    Watch,
+    Repl,
+    Run,
+}
 
-const TEST: &'static str = r#"
-fn foo(x: int, y: int) -> int {
-    let z = x * x
-    z + y * 2
+struct Options {
+    command: Command,
+    inputs: Vec<String>,
+    /// The export `run` calls and the arguments it's given.
+    export: String,
+    args: Vec<String>,
+    /// Where imported modules are looked up.
+    root: Option<String>,
+    output: Option<String>,
+    emit: Emit,
+    /// Whether to write a JavaScript loader next to the wasm.
+    js: bool,
+    error_format: ErrorFormat,
 }
-"#;/*
-class Foo {
-    fn foo(x, y, z) {
-        1 * 2 + 3
-        if 1 {
-            print("foo")
+
+/// Matches `--name value` and `--name=value`.
+fn option_value<I: Iterator<Item=String>>(name: &str, arg: &str, args: &mut I) -> Result<Option<String>, String> {
+    if !arg.starts_with(name) {
+        return Ok(None);
+    }
+    match arg.split_at(name.len()).1 {
+        "" => args.next().map(Some).ok_or_else(|| format!("{} requires a value", name)),
+        value if value.starts_with('=') => Ok(Some(value[1..].to_owned())),
+        _ => Ok(None),
+    }
+}
+
+fn parse_args<I: Iterator<Item=String>>(args: I) -> Result<Options, String> {
+    let mut args = args.peekable();
+    let command = match args.peek().map(|arg| &arg[..]) {
+        Some("watch") => Command::Watch,
+        Some("repl") => Command::Repl,
+        Some("run") => Command::Run,
+        _ => Command::Build,
+    };
+    if command != Command::Build {
+        args.next();
+    }
+
+    let mut inputs = vec![];
+    let mut output = None;
+    let mut emit = Emit::Wasm;
+    let mut js = false;
+    let mut error_format = ErrorFormat::Human;
+    let mut export = "main".to_owned();
+    let mut run_args = vec![];
+    let mut root = None;
+
+    while let Some(arg) = args.next() {
+        // Everything after the file belongs to the program, even if it
+        // looks like an option (e.g. a negative number).
+        if command == Command::Run && !inputs.is_empty() {
+            run_args.push(arg);
+            continue;
         }
-        else {
-            1 + 1
-            2 + 3
+        if let Some(path) = option_value("--root", &arg, &mut args)? {
+            root = Some(path);
+            continue;
         }
-        bar(x, y)
-        baz("hello")
+        if let Some(name) = option_value("--export", &arg, &mut args)? {
+            export = name;
+            continue;
+        }
+        if let Some(name) = option_value("--emit", &arg, &mut args)? {
+            emit = Emit::from_name(&name).ok_or_else(|| format!("unknown stage `{}`", name))?;
+            continue;
+        }
+        if let Some(name) = option_value("--error-format", &arg, &mut args)? {
+            error_format = match &name[..] {
+                "human" => ErrorFormat::Human,
+                "json" => ErrorFormat::Json,
+                _ => return Err(format!("unknown error format `{}`", name)),
+            };
+            continue;
+        }
+        match &arg[..] {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            },
+            "--js" => js = true,
+            "-o" => match args.next() {
+                Some(path) => output = Some(path),
+                None => return Err("-o requires a path".to_owned()),
+            },
+            "-" => inputs.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => inputs.push(arg),
+        }
+    }
+
+    if command == Command::Repl {
+        if !inputs.is_empty() {
+            return Err("repl doesn't take any input files".to_owned());
+        }
+    }
+    else if inputs.is_empty() {
+        return Err("no input files".to_owned());
+    }
+    if js && (emit != Emit::Wasm || command == Command::Run) {
+        return Err("--js can only be used when compiling to wasm".to_owned());
+    }
+    if command == Command::Run && output.is_some() {
+        return Err("-o cannot be used with run".to_owned());
+    }
+    if output.is_some() && inputs.len() > 1 {
+        return Err("-o cannot be used with multiple input files".to_owned());
     }
+    if command == Command::Watch && inputs.iter().any(|input| input == "-") {
+        return Err("stdin can't be watched".to_owned());
+    }
+
+    Ok(Options {
+        command,
+        inputs,
+        export,
+        args: run_args,
+        root,
+        output,
+        emit,
+        js,
+        error_format,
+    })
+}
 
-    fn bar() {
-        "baz"
+fn read_input(input: &str) -> io::Result<(String, String)> {
+    let mut source = String::new();
+    if input == "-" {
+        io::stdin().read_to_string(&mut source)?;
+        return Ok(("stdin".to_owned(), source));
+    }
+
+    File::open(input)?.read_to_string(&mut source)?;
+    let name = Path::new(input)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| input.to_owned());
+    Ok((name, source))
+}
+
+fn output_path(input: &str) -> String {
+    if input == "-" {
+        "output.wasm".to_owned()
+    }
+    else {
+        Path::new(input).with_extension("wasm").to_string_lossy().into_owned()
+    }
+}
+
+fn display_name(input: &str) -> &str {
+    if input == "-" { "<stdin>" } else { input }
+}
+
+/// Writes to the given path, or to stdout if there isn't one. Files are
+/// written next to the destination and then renamed over it, so nothing
+/// watching the output ever sees it half-written.
+fn write_output(output: Option<&str>, bytes: &[u8]) -> Result<(), Vec<Diagnostic>> {
+    let result = match output {
+        Some(path) => {
+            let temp = format!("{}.tmp", path);
+            File::create(&temp)
+                .and_then(|mut file| file.write_all(bytes))
+                .and_then(|()| fs::rename(&temp, path))
+                .map_err(|err| format!("couldn't write {}: {}", path, err))
+        },
+        None => io::stdout().write_all(bytes)
+            .map_err(|err| format!("couldn't write to stdout: {}", err)),
+    };
+    result.map_err(|message| vec![Diagnostic::error(IO_ERROR, None, message)])
+}
+
+fn report(format: ErrorFormat, action: &str, input: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        match format {
+            ErrorFormat::Human => eprint!("{}", diagnostic),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json()),
+        }
+    }
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.is_error()).count();
+    if format == ErrorFormat::Human && errors > 0 {
+        eprintln!(
+            "error: couldn't {} {} due to {} error{}",
+            action, display_name(input), errors, if errors == 1 { "" } else { "s" }
+        );
+    }
+}
+
+/// Compiles a file, giving back any warnings if it succeeds.
+/// Fails with the diagnostics if any of them is an error, so that output
+/// written for a broken input still fails the build.
+fn fail_on_errors(diagnostics: Vec<Diagnostic>) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        Err(diagnostics)
+    }
+    else {
+        Ok(diagnostics)
+    }
+}
+
+fn compile_file(session: &Session, input: &str, output: Option<&str>, emit: Emit, loader: bool) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    let (name, source) = read_input(input).map_err(|err| {
+        vec![Diagnostic::error(IO_ERROR, None, format!("couldn't read {}: {}", display_name(input), err))]
+    })?;
+
+    match emit {
+        Emit::Tokens => {
+            let tokens: Vec<_> = session.lex(Rc::new(source)).collect();
+            let text: String = tokens.iter().map(|token| format!("{:?}\n", token)).collect();
+            write_output(output, text.as_bytes())?;
+            fail_on_errors(diagnostic::collect_tokens(&tokens))
+        },
+        Emit::Ast => {
+            let module = session.parse(&name, &source);
+            write_output(output, format!("{:#?}\n", module).as_bytes())?;
+            fail_on_errors(diagnostic::collect_module(&module))
+        },
+        Emit::Typed => {
+            let program = session.typecheck(session.load(&name, &source)?);
+            write_output(output, format!("{:#?}\n", program).as_bytes())?;
+            fail_on_errors(diagnostic::collect_program(&program))
+        },
+        Emit::Wat => {
+            let program = session.check(&name, &source)?;
+            let module = session.generate(&program)?;
+            write_output(output, wat::print_module(&module).as_bytes())?;
+            Ok(session.lint(&program))
+        },
+        Emit::Wasm => {
+            let program = session.check(&name, &source)?;
+            let code = session.assemble(&program)?;
+            write_output(output, &code)?;
+            if let Some(path) = output.filter(|_| loader) {
+                let path = Path::new(path);
+                let wasm_file = path.file_name().unwrap().to_string_lossy();
+                let js_path = path.with_extension("js").to_string_lossy().into_owned();
+                write_output(Some(&js_path), js::loader(program.entry(), &wasm_file).as_bytes())?;
+            }
+            Ok(session.lint(&program))
+        },
+    }
+}
+
+/// Imports are looked up next to the input unless a root was given.
+fn session(options: &Options, input: &str) -> Session {
+    let root = match options.root {
+        Some(ref root) => Path::new(root),
+        None if input == "-" => Path::new("."),
+        // a bare file name's parent is the empty path
+        None => match Path::new(input).parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        },
+    };
+    Session::with_root(root)
+}
+
+/// Compiles every input, reporting any diagnostics. Returns whether they
+/// all succeeded.
+fn build(options: &Options) -> bool {
+    let mut failed = false;
+    for input in &options.inputs {
+        let session = &session(options, input);
+        let output = match options.emit {
+            Emit::Wasm => Some(options.output.clone().unwrap_or_else(|| output_path(input))),
+            _ => options.output.clone(),
+        };
+        let result = compile_file(session, input, output.as_ref().map(|path| &path[..]), options.emit, options.js);
+        let diagnostics = result.unwrap_or_else(|diagnostics| {
+            failed = true;
+            diagnostics
+        });
+        let file = display_name(input);
+        let diagnostics: Vec<Diagnostic> = diagnostics.into_iter()
+            .map(|diagnostic| diagnostic.in_file(file))
+            .collect();
+        report(options.error_format, "compile", input, &diagnostics);
+    }
+    !failed
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// The files an input's build read: the input and every module it
+/// imports, or `None` if it couldn't be loaded.
+fn loaded_files(options: &Options, input: &str) -> Option<Vec<PathBuf>> {
+    let (name, source) = read_input(input).ok()?;
+    let program = session(options, input).load(&name, &source).ok()?;
+    Some(program.files.values().map(PathBuf::from).collect())
+}
+
+/// When each of the inputs and the modules they import was last modified.
+fn stamps(options: &Options, imports: &[Vec<PathBuf>]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files: Vec<PathBuf> = options.inputs.iter().map(PathBuf::from).collect();
+    files.extend(imports.iter().flatten().cloned());
+    files.sort();
+    files.dedup();
+    files.into_iter()
+        .map(|file| {
+            let stamp = modified(&file);
+            (file, stamp)
+        })
+        .collect()
+}
+
+/// Rebuilds whenever an input or a module it imported on the last build
+/// changes. An input that can't be loaded keeps watching the modules it
+/// imported before.
+fn watch(options: &Options) -> ! {
+    let mut imports = vec![vec![]; options.inputs.len()];
+    let mut last_seen = None;
+    loop {
+        if last_seen.as_ref() != Some(&stamps(options, &imports)) {
+            for (input, imported) in options.inputs.iter().zip(&mut imports) {
+                if let Some(files) = loaded_files(options, input) {
+                    *imported = files;
+                }
+            }
+            last_seen = Some(stamps(options, &imports));
+            if build(options) {
+                eprintln!("[watch] compiled {}", options.inputs.join(", "));
+            }
+            eprintln!("[watch] waiting for changes...");
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn repl() -> io::Result<()> {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            println!();
+            return Ok(());
+        }
+        input.push_str(&line);
+        if repl.needs_more_input(&input) {
+            continue;
+        }
+
+        match repl.eval(&input) {
+            Ok(results) => for result in results {
+                println!("{}", result);
+            },
+            Err(diagnostics) => for diagnostic in diagnostics {
+                eprint!("{}", diagnostic);
+            },
+        }
+        input.clear();
+    }
+}
+
+/// Compiles the input and calls the chosen export, printing its result.
+fn run(options: &Options) -> bool {
+    let input = &options.inputs[0];
+    let session = session(options, input);
+    let result = read_input(input)
+        .map_err(|err| {
+            vec![Diagnostic::error(IO_ERROR, None, format!("couldn't read {}: {}", display_name(input), err))]
+        })
+        .and_then(|(name, source)| session.check(&name, &source))
+        .and_then(|program| {
+            let warnings = session.lint(&program);
+            session.execute(&program, &options.export, &options.args)
+                .map(|value| (value, warnings.clone()))
+                .map_err(|errors| warnings.into_iter().chain(errors).collect())
+        });
+    let (value, diagnostics) = match result {
+        Ok((value, warnings)) => (Some(value), warnings),
+        Err(diagnostics) => (None, diagnostics),
+    };
+    let diagnostics: Vec<Diagnostic> = diagnostics.into_iter()
+        .map(|diagnostic| diagnostic.in_file(display_name(input)))
+        .collect();
+    report(options.error_format, "run", input, &diagnostics);
+    match value {
+        Some(value) => {
+            println!("{}", value);
+            true
+        },
+        None => false,
     }
 }
-"#;*/
 
 fn main() {
-    let lexer = lexer::Lexer::new();
-    let tokens = lexer.lex(Rc::new(TEST.to_owned()));
-    /*for span in &tokens {
-        let span = span.map(|token| format!("{:?}", token));
-        println!("{}", span);
-    }*/
-    let iter: Box<Iterator<Item=Span<Token>>> = Box::new(tokens.into_iter());
-    let module = parser::parse_module("stdin", &mut iter.peekable());
-    let module = typecheck::typecheck_module(module);
-    println!("{:#?}", module);
-    let module = compiler::compile_module(&module);
-    //println!("{:#?}", module);
-
-    let mut file = File::create("output.wasm").unwrap();
-    let mut code = vec![];
-    module.dump(&mut code);
-    file.write(&mut code).unwrap();
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        },
+    };
+
+    match options.command {
+        Command::Build => if !build(&options) {
+            process::exit(1);
+        },
+        Command::Watch => watch(&options),
+        Command::Run => if !run(&options) {
+            process::exit(1);
+        },
+        Command::Repl => if let Err(err) = repl() {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        },
+    }
 }
@@ -1,40 +1,253 @@
-use ast::{Expr, ParseError, BinOp, Item, FunctionArgument, Module, Statement};
-use lexer::Token;
-use std::iter::Peekable;
+use ast::{Expr, Fragment, ParseError, BinOp, Item, FunctionArgument, Module, Statement};
+use lexer::{self, LexError, StringPart, Token};
+use std::mem;
 use span::Span;
-use typecheck::{Typed, Type};
+use typecheck::{Typed, Type, TypeError};
+
+/// The tokens being parsed. Streams end with `Token::Eof`, which is never
+/// taken: once it's reached it stays the next token, so there's always one
+/// to look at.
+pub struct TokenIterator<'a> {
+    tokens: Box<dyn Iterator<Item=Span<Token>> + 'a>,
+    next: Span<Token>,
+    /// The brackets taken that haven't been closed yet, innermost last.
+    unclosed: Vec<Span<Token>>,
+    /// The errors in the tokens taken so far.
+    lex_errors: Vec<Span<LexError>>,
+}
 
-pub type TokenIterator = Peekable<Box<Iterator<Item=Span<Token>>>>;
+impl<'a> TokenIterator<'a> {
+    /// `end` is where the tokens stop, in case they run out without an
+    /// `Eof`.
+    pub fn new<I: Iterator<Item=Span<Token>> + 'a>(tokens: I, end: Span<()>) -> TokenIterator<'a> {
+        let mut tokens: Box<dyn Iterator<Item=Span<Token>> + 'a> = Box::new(tokens);
+        let next = tokens.next().unwrap_or_else(|| end.replace(Token::Eof));
+        TokenIterator {
+            tokens,
+            next,
+            unclosed: vec![],
+            lex_errors: vec![],
+        }
+    }
 
-fn parse_if(iter: &mut TokenIterator) -> Span<Expr> {
-    let condition = parse_expr(iter);
+    pub fn peek(&self) -> &Span<Token> {
+        &self.next
+    }
 
-    match iter.next().unwrap().split() {
-        (_span, Token::CurlyLeft) => (),
-        (span, token) => return span.replace(Expr::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "{",
-        }))
-    };
+    /// Takes the lexing errors in the tokens taken so far, including the
+    /// ones in the holes of interpolated strings.
+    pub fn take_lex_errors(&mut self) -> Vec<Span<LexError>> {
+        mem::take(&mut self.lex_errors)
+    }
 
-    let mut branch_then = vec![];
-    let branch_then_end;
+    /// Takes the next token, or gives `Eof` again at the end.
+    pub fn advance(&mut self) -> Span<Token> {
+        if let Token::Eof = *self.next.get_value() {
+            return self.next.clone();
+        }
+        let following = match self.tokens.next() {
+            Some(token) => token,
+            None => {
+                let len = self.next.text().len();
+                self.next.subspan(len, len).replace(Token::Eof)
+            },
+        };
+        let token = mem::replace(&mut self.next, following);
+        lexer::collect_errors(&mut self.lex_errors, &token);
+
+        let open = match *token.get_value() {
+            Token::ParenLeft | Token::CurlyLeft => {
+                self.unclosed.push(token.clone());
+                return token;
+            },
+            Token::ParenRight => Token::ParenLeft,
+            Token::CurlyRight => Token::CurlyLeft,
+            _ => return token,
+        };
+        // a closing bracket also closes any left open inside it
+        let index = self.unclosed.iter()
+            .rposition(|bracket| mem::discriminant(bracket.get_value()) == mem::discriminant(&open));
+        if let Some(index) = index {
+            self.unclosed.truncate(index);
+        }
+        token
+    }
+}
+
+/// An error for a token that doesn't fit. Running out of tokens is reported
+/// at the innermost bracket still open, since that's where the mistake
+/// usually is.
+fn unexpected(iter: &TokenIterator, token: Span<Token>, expected: &'static str) -> Span<ParseError> {
+    match (token.get_value(), iter.unclosed.last()) {
+        (&Token::Eof, Some(bracket)) => bracket.replace(ParseError::UnexpectedEof {
+            unclosed: bracket.clone(),
+        }),
+        _ => token.replace(ParseError::UnexpectedToken {
+            token: token.clone(),
+            expected,
+        }),
+    }
+}
+
+/// Skips any newlines and semicolons, returning whether the last one was a
+/// newline.
+pub fn skip_terminators(iter: &mut TokenIterator) -> bool {
+    let mut after_newline = false;
     loop {
-        branch_then.push(parse_expr(iter));
+        match *iter.peek().get_value() {
+            Token::Newline => after_newline = true,
+            Token::Semicolon => after_newline = false,
+            _ => return after_newline,
+        }
+        iter.advance();
+    }
+}
 
-        match iter.peek().map(ToOwned::to_owned).unwrap().split() {
-            (span, Token::CurlyRight) => {
-                branch_then_end = span;
-                iter.next();
-                break;
+/// Skips to the end of the current statement, leaving its terminator.
+/// Brackets opened in it are skipped whole, and stray closing parentheses
+/// are skipped too.
+fn skip_statement(iter: &mut TokenIterator) {
+    let mut depth = 0;
+    loop {
+        match *iter.peek().get_value() {
+            Token::Eof => return,
+            Token::Newline | Token::Semicolon | Token::CurlyRight if depth == 0 => return,
+            Token::ParenLeft | Token::CurlyLeft => depth += 1,
+            Token::ParenRight | Token::CurlyRight if depth > 0 => depth -= 1,
+            _ => (),
+        }
+        iter.advance();
+    }
+}
+
+/// Skips past a mistake in an expression, up to a token that could come
+/// after the expression, which is left. Parentheses opened along the way
+/// are skipped whole.
+fn recover(iter: &mut TokenIterator) {
+    let mut depth = 0;
+    loop {
+        match *iter.peek().get_value() {
+            Token::CurlyRight | Token::Eof => return,
+            Token::Newline | Token::Semicolon | Token::Comma | Token::ParenRight | Token::CurlyLeft if depth == 0 => return,
+            Token::ParenLeft => depth += 1,
+            Token::ParenRight => depth -= 1,
+            _ => (),
+        }
+        iter.advance();
+    }
+}
+
+/// Skips the rest of a broken function or class header, up to the `{` that
+/// opens its body. Stops early at anything that looks like the start of
+/// another item or the end of the block it's in.
+fn skip_header(iter: &mut TokenIterator) {
+    loop {
+        match *iter.peek().get_value() {
+            Token::CurlyLeft | Token::CurlyRight | Token::Fn | Token::Class | Token::Import | Token::Doc(_) |
+            Token::ModuleDoc(_) | Token::Eof => return,
+            _ => (),
+        }
+        iter.advance();
+    }
+}
+
+/// Skips the rest of a broken item, up to the start of the next one or the
+/// `}` closing the class it's in. Blocks in it are skipped whole.
+fn skip_item(iter: &mut TokenIterator) {
+    let mut depth = 0;
+    loop {
+        match *iter.peek().get_value() {
+            Token::Eof => return,
+            Token::CurlyRight | Token::Fn | Token::Class | Token::Import | Token::Doc(_) | Token::ModuleDoc(_)
+                if depth == 0 => return,
+            Token::CurlyLeft => depth += 1,
+            Token::CurlyRight => depth -= 1,
+            _ => (),
+        }
+        iter.advance();
+    }
+}
+
+/// Takes the next token if it's the same kind as `token`, or gives an error
+/// for it without taking it.
+fn expect(iter: &mut TokenIterator, token: Token, expected: &'static str) -> Result<Span<()>, Span<ParseError>> {
+    let next = iter.peek().clone();
+    if mem::discriminant(next.get_value()) == mem::discriminant(&token) {
+        iter.advance();
+        Ok(next.peek())
+    }
+    else {
+        Err(unexpected(iter, next, expected))
+    }
+}
+
+fn expect_ident(iter: &mut TokenIterator, expected: &'static str) -> Result<Span<String>, Span<ParseError>> {
+    match iter.peek().clone().split() {
+        (span, Token::Ident(ident)) => {
+            iter.advance();
+            Ok(span.replace(ident))
+        },
+        (span, token) => Err(unexpected(iter, span.replace(token), expected)),
+    }
+}
+
+/// Parses statements up to the `}` closing a block, which is consumed.
+/// Statements have to be separated by a newline or `;`, and anything else
+/// after one is reported and skipped.
+fn parse_block<T, P, E>(iter: &mut TokenIterator, parse: P, error: E) -> (Vec<Span<T>>, Span<()>)
+    where P: Fn(&mut TokenIterator) -> Span<T>, E: Fn(ParseError) -> T
+{
+    let mut statements = vec![];
+    loop {
+        let after_newline = skip_terminators(iter);
+        let (span, token) = iter.peek().clone().split();
+        match token {
+            Token::CurlyRight => {
+                iter.advance();
+                return (statements, span);
+            },
+            Token::Eof => {
+                statements.push(unexpected(iter, span.replace(token), "}").map(&error));
+                return (statements, span);
+            },
+            // these would continue the previous line in most languages, so
+            // starting a statement with them is probably a mistake
+            Token::Plus | Token::Minus | Token::Aster | Token::Slash | Token::Dot | Token::ParenLeft if after_newline => {
+                statements.push(span.replace(error(ParseError::AmbiguousLineStart {
+                    token: span.replace(token),
+                })));
+                skip_statement(iter);
+                continue;
+            },
+            _ => (),
+        }
+
+        statements.push(parse(iter));
+
+        match iter.peek().clone().split() {
+            (_span, Token::Newline) | (_span, Token::Semicolon) | (_span, Token::CurlyRight) | (_span, Token::Eof) => (),
+            // a doc comment runs to the end of its line
+            (_span, ref token) if token.is_doc() => (),
+            (span, token) => {
+                statements.push(unexpected(iter, span.replace(token), "newline or `;`").map(&error));
+                skip_statement(iter);
             },
-            _ => continue,
         }
     }
+}
+
+fn parse_if(iter: &mut TokenIterator) -> Span<Expr> {
+    let condition = parse_expr(iter);
 
-    match iter.peek().map(ToOwned::to_owned).unwrap().split() {
+    if let Err(err) = expect(iter, Token::CurlyLeft, "{") {
+        return err.map(Expr::Error);
+    }
+
+    let (branch_then, branch_then_end) = parse_block(iter, parse_expr, Expr::Error);
+
+    match iter.peek().clone().split() {
         (_span, Token::Else) => {
-            iter.next();
+            iter.advance();
         },
         _ => return Span::bridge(condition.peek(), branch_then_end, Expr::If {
             condition: Box::new(condition.map(Typed::new)),
@@ -43,29 +256,12 @@ fn parse_if(iter: &mut TokenIterator) -> Span<Expr> {
         }),
     }
 
-    match iter.next().unwrap().split() {
-        (_span, Token::CurlyLeft) => (),
-        (span, token) => return span.replace(Expr::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "{",
-        }))
-    };
-
-    let mut branch_else = vec![];
-    let branch_else_end;
-    loop {
-        branch_else.push(parse_expr(iter));
-
-        match iter.peek().map(ToOwned::to_owned).unwrap().split() {
-            (span, Token::CurlyRight) => {
-                branch_else_end = span;
-                iter.next();
-                break;
-            },
-            _ => continue,
-        }
+    if let Err(err) = expect(iter, Token::CurlyLeft, "{") {
+        return err.map(Expr::Error);
     }
 
+    let (branch_else, branch_else_end) = parse_block(iter, parse_expr, Expr::Error);
+
     Span::bridge(condition.peek(), branch_else_end, Expr::If {
         condition: Box::new(condition.map(Typed::new)),
         branch_then: branch_then.into_iter().map(|span| span.map(Typed::new)).collect(),
@@ -74,42 +270,102 @@ fn parse_if(iter: &mut TokenIterator) -> Span<Expr> {
 }
 
 fn parse_const(iter: &mut TokenIterator) -> Span<Expr> {
-    let (span, token) = iter.next().unwrap().split();
-    match token {
-        Token::If => parse_if(iter),
-        Token::Ident(ident) => span.replace(Expr::Variable(ident)),
-        Token::Integer(int) => span.replace(Expr::ConstInteger(int)),
-        Token::Number(num) => span.replace(Expr::ConstNumber(num)),
-        Token::String(string) => span.replace(Expr::ConstString(string)),
-        token => span.replace(Expr::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "constant",
-        }))
+    let (span, token) = iter.peek().clone().split();
+    let expr = match token {
+        Token::Integer(int) => Expr::ConstInteger(int),
+        Token::Long(int) => Expr::ConstLong(int),
+        Token::Number(num) => Expr::ConstNumber(num),
+        Token::String(string) => Expr::ConstString(string),
+        Token::Char(ch) => Expr::ConstChar(ch),
+        Token::If => {
+            iter.advance();
+            return parse_if(iter);
+        },
+        Token::Ident(ident) => {
+            iter.advance();
+            return parse_path(iter, span.replace(ident)).map(Expr::Variable);
+        },
+        Token::InterpolatedString(parts) => {
+            iter.advance();
+            return parse_interpolated(span, parts);
+        },
+        token => {
+            recover(iter);
+            return unexpected(iter, span.replace(token), "constant").map(Expr::Error);
+        },
+    };
+    iter.advance();
+    span.replace(expr)
+}
+
+/// Parses the holes of an interpolated string, which have to hold one
+/// expression each.
+fn parse_interpolated(span: Span<()>, parts: Vec<StringPart>) -> Span<Expr> {
+    let mut fragments = vec![];
+    for part in parts {
+        let tokens = match part {
+            StringPart::Text(text) => {
+                fragments.push(Fragment::Text(text));
+                continue;
+            },
+            StringPart::Hole(tokens) => tokens,
+        };
+        // the `Eof` ending the tokens stands for the closing `}`
+        let mut iter = TokenIterator::new(tokens.into_iter(), span.clone());
+        let expr = parse_expr(&mut iter);
+        match iter.advance().split() {
+            (_span, Token::Eof) => (),
+            (span, token) => return unexpected(&iter, span.replace(token), "}").map(Expr::Error),
+        }
+        fragments.push(Fragment::Hole(expr.map(Typed::new)));
     }
+    span.replace(Expr::Interpolated(fragments))
+}
+
+/// Reads the rest of a dotted name like `foo.bar.baz`.
+fn parse_path(iter: &mut TokenIterator, first: Span<String>) -> Span<String> {
+    let mut path = first;
+    while let (dot, Token::Dot) = iter.peek().clone().split() {
+        iter.advance();
+        match expect_ident(iter, "ident") {
+            Ok(ident) => {
+                let name = format!("{}.{}", path.get_value(), ident.get_value());
+                path = Span::bridge(path.peek(), ident, name);
+            },
+            // a trailing dot leaves an undefined name for the typechecker
+            // to report
+            Err(_) => return Span::bridge(path.peek(), dot, format!("{}.", path.get_value())),
+        }
+    }
+    path
 }
 
 fn parse_call(iter: &mut TokenIterator) -> Span<Expr> {
     let lhs = parse_const(iter);
 
-    let (span, token) = iter.peek().map(ToOwned::to_owned).unwrap().split();
+    let (span, token) = iter.peek().clone().split();
     match token {
         Token::ParenLeft => {
-            iter.next();
+            iter.advance();
             let mut args = vec![];
             loop {
-                let expr = parse_expr(iter);
-                args.push(expr);
-                let (span, token) = iter.next().unwrap().split();
-                match token {
-                    Token::Comma => continue,
-                    Token::ParenRight => break,
-                    token => {
-                        args.push(span.replace(Expr::Error(ParseError::UnexpectedToken {
-                            token: span.replace(token),
-                            expected: ", or )",
-                        })));
+                args.push(parse_expr(iter));
+                match iter.peek().clone().split() {
+                    (_span, Token::Comma) | (_span, Token::ParenRight) => (),
+                    (span, token) => {
+                        args.push(unexpected(iter, span.replace(token), ", or )").map(Expr::Error));
+                        recover(iter);
+                    },
+                }
+                match *iter.peek().get_value() {
+                    Token::Comma => {
+                        iter.advance();
+                    },
+                    Token::ParenRight => {
+                        iter.advance();
                         break;
-                    }
+                    },
+                    _ => break,
                 }
             }
             span.replace(Expr::Call {
@@ -121,315 +377,445 @@ fn parse_call(iter: &mut TokenIterator) -> Span<Expr> {
     }
 }
 
-fn parse_mul(iter: &mut TokenIterator) -> Span<Expr> {
-    let lhs = parse_call(iter);
+/// What an operator after an expression does with it.
+enum Operator {
+    Binary(BinOp),
+    Cast,
+}
 
-    let (span, token) = iter.peek().map(ToOwned::to_owned).unwrap().split();
-    match token {
-        Token::Aster => {
-            iter.next();
-            span.replace(Expr::BinOp {
-                op: BinOp::Mul,
-                lhs: Box::new(lhs.map(Typed::new)),
-                rhs: Box::new(parse_mul(iter).map(Typed::new)),
-            })
-        },
-        Token::Slash => {
-            iter.next();
-            span.replace(Expr::BinOp {
-                op: BinOp::Div,
-                lhs: Box::new(lhs.map(Typed::new)),
-                rhs: Box::new(parse_mul(iter).map(Typed::new)),
-            })
-        }
-        _ => lhs
+/// The operators that can follow an expression, with how tightly each
+/// binds the expression before it and the one after it. Binding the one
+/// after more tightly makes a chain like `a - b - c` group to the left, and
+/// binding it the same makes it group to the right. A new operator only
+/// needs a line here.
+fn operator(token: &Token) -> Option<(Operator, u8, u8)> {
+    match *token {
+        Token::Plus => Some((Operator::Binary(BinOp::Add), 1, 2)),
+        Token::Minus => Some((Operator::Binary(BinOp::Sub), 1, 2)),
+        Token::Aster => Some((Operator::Binary(BinOp::Mul), 3, 4)),
+        Token::Slash => Some((Operator::Binary(BinOp::Div), 3, 4)),
+        Token::As => Some((Operator::Cast, 5, 6)),
+        _ => None,
     }
-
 }
 
-fn parse_add(iter: &mut TokenIterator) -> Span<Expr> {
-    let lhs = parse_mul(iter);
+/// Parses an expression made of operators that bind at least as tightly as
+/// `min`, leaving any looser one for the caller.
+fn parse_operators(iter: &mut TokenIterator, min: u8) -> Span<Expr> {
+    let mut lhs = parse_call(iter);
 
-    let (span, token) = iter.peek().map(ToOwned::to_owned).unwrap().split();
-    match token {
-        Token::Plus => {
-            iter.next();
-            span.replace(Expr::BinOp {
-                op: BinOp::Add,
-                lhs: Box::new(lhs.map(Typed::new)),
-                rhs: Box::new(parse_add(iter).map(Typed::new)),
-            })
-        },
-        Token::Minus => {
-            iter.next();
-            span.replace(Expr::BinOp {
-                op: BinOp::Sub,
-                lhs: Box::new(lhs.map(Typed::new)),
-                rhs: Box::new(parse_add(iter).map(Typed::new)),
-            })
-        }
-        _ => lhs
+    loop {
+        let (span, token) = iter.peek().clone().split();
+        let (op, right) = match operator(&token) {
+            Some((op, left, right)) if left >= min => (op, right),
+            _ => return lhs,
+        };
+        iter.advance();
+
+        lhs = match op {
+            Operator::Binary(op) => {
+                let rhs = parse_operators(iter, right);
+                span.replace(Expr::BinOp {
+                    op,
+                    lhs: Box::new(lhs.map(Typed::new)),
+                    rhs: Box::new(rhs.map(Typed::new)),
+                })
+            },
+            Operator::Cast => {
+                let to = match parse_type(iter).split() {
+                    (span, Ok(type_info)) => span.replace(type_info),
+                    (span, Err(err)) => return span.replace(Expr::Error(err)),
+                };
+                let (first, last) = (lhs.peek(), to.peek());
+                Span::bridge(first, last, Expr::Cast {
+                    value: Box::new(lhs.map(Typed::new)),
+                    to,
+                })
+            },
+        };
     }
 }
 
 pub fn parse_expr(iter: &mut TokenIterator) -> Span<Expr> {
-    parse_add(iter)
+    parse_operators(iter, 0)
 }
 
 fn parse_let(iter: &mut TokenIterator) -> Span<Statement> {
-    let start_span = match iter.next().unwrap().split() {
+    let start_span = match iter.advance().split() {
         (span, Token::Let) => span,
-        (span, token) => return span.replace(Statement::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "let",
-        }))
+        (span, token) => return unexpected(iter, span.replace(token), "let").map(Statement::Error)
     };
 
-    let name = match iter.next().unwrap().split() {
-        (span, Token::Ident(name)) => span.replace(name),
-        (span, token) => return span.replace(Statement::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "ident",
-        }))
-    };
-
-    match iter.next().unwrap().split() {
-        (_span, Token::Equals) => (),
-        (span, token) => return span.replace(Statement::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "=",
-        }))
+    let name = expect_ident(iter, "ident");
+    let name = match name.and_then(|name| expect(iter, Token::Equals, "=").map(|_| name)) {
+        Ok(name) => name,
+        Err(err) => {
+            skip_statement(iter);
+            return err.map(Statement::Error);
+        },
     };
 
     let expr = parse_expr(iter);
 
     Span::bridge(start_span, expr.peek(), Statement::Let {
-        name: name,
+        name,
         value: expr.map(Typed::new),
     })
 }
 
-fn parse_statement(iter: &mut TokenIterator) -> Span<Statement> {
-    match iter.peek().map(ToOwned::to_owned).unwrap().split() {
+pub fn parse_statement(iter: &mut TokenIterator) -> Span<Statement> {
+    match iter.peek().clone().split() {
         (_span, Token::Let) => parse_let(iter),
-        (_span, Token::Fn) | (_span, Token::Class) => parse_item(iter).map(Statement::Item),
+        (_span, Token::Fn) | (_span, Token::Class) | (_span, Token::Doc(_)) | (_span, Token::ModuleDoc(_)) =>
+            parse_item(iter).map(Statement::Item),
         _ => parse_expr(iter).map(Typed::new).map(Statement::Expr)
     }
 }
 
 fn parse_type(iter: &mut TokenIterator) -> Span<Result<Type, ParseError>> {
-    match iter.next().unwrap().split() {
-        (span, Token::Ident(ident)) => match &ident[..] {
-            "int" => span.replace(Ok(Type::Integer)),
-            "real" => span.replace(Ok(Type::Real)),
-            "string" => span.replace(Ok(Type::String)),
-            "bool" => span.replace(Ok(Type::Boolean)),
-            _ => span.replace(Err(ParseError::UnexpectedToken {
-                token: span.replace(Token::Ident(ident)),
-                expected: "type",
-            })),
+    match iter.peek().clone().split() {
+        (span, Token::Ident(ident)) => {
+            iter.advance();
+            match &ident[..] {
+                "int" => span.replace(Ok(Type::Integer)),
+                "i64" => span.replace(Ok(Type::Long)),
+                "real" => span.replace(Ok(Type::Real)),
+                "string" => span.replace(Ok(Type::String)),
+                "bool" => span.replace(Ok(Type::Boolean)),
+                "char" => span.replace(Ok(Type::Char)),
+                _ => unexpected(iter, span.replace(Token::Ident(ident)), "type").map(Err),
+            }
         },
-        (span, token) => span.replace(Err(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "type",
-        })),
+        (span, token) => unexpected(iter, span.replace(token), "type").map(Err),
     }
 }
 
-fn parse_func_arg(iter: &mut TokenIterator) -> Span<Result<FunctionArgument, ParseError>> {
-    let name = match iter.next().unwrap().split() {
-        (span, Token::Ident(ident)) => span.replace(ident),
-        (span, token) => return span.replace(Err(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "ident",
-        })),
+/// Parses an argument like `x: int`. An argument whose type is missing is
+/// still kept, so uses of it in the body aren't reported as undefined.
+fn parse_func_arg(iter: &mut TokenIterator, errors: &mut Vec<Span<ParseError>>) -> Option<Span<FunctionArgument>> {
+    let name = match expect_ident(iter, "ident") {
+        Ok(name) => name,
+        Err(err) => {
+            errors.push(err);
+            return None;
+        },
     };
 
-    match iter.next().unwrap().split() {
-        (_span, Token::Colon) => (),
-        (span, token) => return span.replace(Err(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: ":",
-        })),
+    let type_desc = match expect(iter, Token::Colon, ":") {
+        Ok(_) => parse_type(iter),
+        Err(err) => err.map(Err),
     };
-
-    let type_info = match parse_type(iter).split() {
+    let type_desc = match type_desc.split() {
         (span, Ok(type_info)) => span.replace(type_info),
-        (span, Err(err)) => return span.replace(Err(err)),
+        (span, Err(err)) => {
+            errors.push(span.replace(err));
+            span.replace(Type::Error(TypeError::ParseError))
+        },
     };
 
-    name.clone().replace(Ok(FunctionArgument {
-        name: name,
-        type_desc: type_info,
+    Some(name.clone().replace(FunctionArgument {
+        name,
+        type_desc,
     }))
 }
 
-fn parse_func(iter: &mut TokenIterator) -> Span<Item> {
-    let start_span = match iter.next().unwrap().split() {
-        (span, Token::Fn) => span,
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "fn",
-        }))
-    };
+/// Parses a function's arguments and result type, up to the `{` opening
+/// its body. A mistake in one argument only skips to the next. Gives no
+/// error if the mistake that stopped it is already in `errors`.
+fn parse_signature(
+    iter: &mut TokenIterator,
+    args: &mut Vec<Span<FunctionArgument>>,
+    errors: &mut Vec<Span<ParseError>>,
+) -> Result<Span<Type>, Option<Span<ParseError>>> {
+    expect(iter, Token::ParenLeft, "(")?;
+
+    if let Token::ParenRight = *iter.peek().get_value() {
+        iter.advance();
+    }
+    else {
+        loop {
+            let error_count = errors.len();
+            args.extend(parse_func_arg(iter, errors));
+            let failed = errors.len() > error_count;
+            if failed {
+                recover(iter);
+            }
 
-    let name = match iter.next().unwrap().split() {
-        (span, Token::Ident(ident)) => span.replace(ident),
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "ident",
-        })),
-    };
+            match iter.peek().clone().split() {
+                (_span, Token::Comma) => {
+                    iter.advance();
+                },
+                (_span, Token::ParenRight) => {
+                    iter.advance();
+                    break;
+                },
+                // a `)` missing after a broken argument is likely part of
+                // the same mistake
+                _ if failed => return Err(None),
+                (span, token) => return Err(Some(unexpected(iter, span.replace(token), ", or )"))),
+            }
+        }
+    }
 
-    match iter.next().unwrap().split() {
-        (_span, Token::ParenLeft) => (),
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "(",
-        }))
-    };
+    expect(iter, Token::Arrow, "->")?;
 
-    let mut args = vec![];
-    match iter.peek().map(ToOwned::to_owned).unwrap().split() {
-        (_span, Token::ParenRight) => {
-            iter.next();
-        },
-        _ => loop {
-            match parse_func_arg(iter).split() {
-                (span, Ok(arg)) => args.push(span.replace(arg)),
-                (span, Err(err)) => return span.replace(Item::Error(err)),
-            };
-
-            match iter.next().unwrap().split() {
-                (_span, Token::Comma) => continue,
-                (_span, Token::ParenRight) => break,
-                (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-                    token: span.replace(token),
-                    expected: ", or )",
-                })),
-            };
-        }
+    match parse_type(iter).split() {
+        (span, Ok(type_info)) => Ok(span.replace(type_info)),
+        (span, Err(err)) => Err(Some(span.replace(err))),
     }
+}
 
-    match iter.next().unwrap().split() {
-        (_span, Token::Arrow) => (),
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "->",
-        }))
+/// Parses a function. Mistakes in its signature are reported at the top of
+/// its body, so the arguments and body that did parse can still be checked.
+fn parse_func(iter: &mut TokenIterator, doc: Option<Span<String>>) -> Span<Item> {
+    let start_span = match iter.advance().split() {
+        (span, Token::Fn) => span,
+        (span, token) => return unexpected(iter, span.replace(token), "fn").map(Item::Error)
     };
 
-    let type_info = match parse_type(iter).split() {
-        (span, Ok(type_info)) => span.replace(type_info),
-        (span, Err(err)) => return span.replace(Item::Error(err)),
+    let name = match expect_ident(iter, "ident") {
+        Ok(name) => name,
+        Err(err) => {
+            skip_item(iter);
+            return err.map(Item::Error);
+        },
     };
 
-    match iter.next().unwrap().split() {
-        (_span, Token::CurlyLeft) => (),
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "{",
-        }))
+    let mut args = vec![];
+    let mut errors = vec![];
+    let result = match parse_signature(iter, &mut args, &mut errors) {
+        Ok(result) => result,
+        Err(err) => {
+            let span = iter.peek().peek();
+            errors.extend(err);
+            skip_header(iter);
+            span.replace(Type::Error(TypeError::ParseError))
+        },
     };
 
-    let mut body = vec![];
-    let end_span;
-
-    loop {
-        match iter.peek().map(ToOwned::to_owned).unwrap().split() {
-            (span, Token::CurlyRight) => {
-                iter.next();
-                end_span = span;
-                break;
-            },
-            _ => (),
-        }
-
-        body.push(parse_statement(iter));
-    }
+    let (body, end_span) = match expect(iter, Token::CurlyLeft, "{") {
+        Ok(_) => parse_block(iter, parse_statement, Statement::Error),
+        Err(err) => {
+            // a body that's missing after a broken signature is expected
+            if errors.is_empty() {
+                errors.push(err);
+            }
+            (vec![], result.peek())
+        },
+    };
+    let body = errors.into_iter()
+        .map(|err| err.map(Statement::Error))
+        .chain(body)
+        .collect();
 
     Span::bridge(start_span, end_span, Item::Function {
-        name: name,
-        args: args,
-        body: body,
-        result: type_info,
+        doc,
+        name,
+        args,
+        body,
+        result,
     })
 }
 
-fn parse_class(iter: &mut TokenIterator) -> Span<Item> {
-    let start_span = match iter.next().unwrap().split() {
+/// Parses a class. A broken member is skipped up to the next one.
+fn parse_class(iter: &mut TokenIterator, doc: Option<Span<String>>) -> Span<Item> {
+    let start_span = match iter.advance().split() {
         (span, Token::Class) => span,
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "class",
-        }))
-    };
-
-    let name = match iter.next().unwrap().split() {
-        (span, Token::Ident(ident)) => span.replace(ident),
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "ident",
-        })),
+        (span, token) => return unexpected(iter, span.replace(token), "class").map(Item::Error)
     };
 
-    match iter.next().unwrap().split() {
-        (_span, Token::CurlyLeft) => (),
-        (span, token) => return span.replace(Item::Error(ParseError::UnexpectedToken {
-            token: span.replace(token),
-            expected: "{",
-        }))
+    let name = match expect_ident(iter, "ident") {
+        Ok(name) => name,
+        Err(err) => {
+            skip_item(iter);
+            return err.map(Item::Error);
+        },
     };
 
     let mut members = vec![];
+    if let Err(err) = expect(iter, Token::CurlyLeft, "{") {
+        members.push(err.map(Item::Error));
+        skip_header(iter);
+        if expect(iter, Token::CurlyLeft, "{").is_err() {
+            return Span::bridge(start_span, name.peek(), Item::Class {
+                doc,
+                name,
+                members,
+            });
+        }
+    }
+
     let end_span;
     loop {
-        members.push(parse_item(iter));
-
-        match iter.peek().map(ToOwned::to_owned).unwrap().split() {
+        skip_terminators(iter);
+        match iter.peek().clone().split() {
             (span, Token::CurlyRight) => {
-                iter.next();
+                iter.advance();
                 end_span = span;
                 break;
             },
-            _ => continue,
-        };
+            (span, Token::Eof) => {
+                members.push(unexpected(iter, span.replace(Token::Eof), "}").map(Item::Error));
+                end_span = span;
+                break;
+            },
+            _ => members.push(parse_item(iter)),
+        }
     }
 
     Span::bridge(start_span, end_span, Item::Class {
-        name: name,
-        members: members,
+        doc,
+        name,
+        members,
     })
 }
 
+/// Reads a run of `///` comments, or of `//!` comments if `module` is set,
+/// joining their lines.
+fn parse_doc(iter: &mut TokenIterator, module: bool) -> Option<Span<String>> {
+    let mut doc: Option<Span<String>> = None;
+    loop {
+        let line = match iter.peek().clone().split() {
+            (span, Token::Doc(line)) if !module => span.replace(line),
+            (span, Token::ModuleDoc(line)) if module => span.replace(line),
+            _ => return doc,
+        };
+        iter.advance();
+        doc = Some(match doc {
+            Some(doc) => {
+                let text = format!("{}\n{}", doc.get_value(), line.get_value());
+                Span::bridge(doc, line, text)
+            },
+            None => line,
+        });
+    }
+}
+
+/// Parses an item along with the doc comment in front of it. A doc comment
+/// that isn't followed by an item is returned on its own.
 pub fn parse_item(iter: &mut TokenIterator) -> Span<Item> {
-    match iter.peek().map(ToOwned::to_owned).unwrap().split() {
-        (_span, Token::Fn) => parse_func(iter),
-        (_span, Token::Class) => parse_class(iter),
-        (span, token) => {
-            iter.next();
-            span.replace(Item::Error(ParseError::UnexpectedToken {
-                token: span.replace(token),
-                expected: "item",
-            }))
+    let doc = parse_doc(iter, false);
+    match iter.peek().clone().split() {
+        (_span, Token::Fn) => return parse_func(iter, doc),
+        (_span, Token::Class) => return parse_class(iter, doc),
+        _ => (),
+    }
+
+    let stray = match doc {
+        Some(doc) => Some(doc.replace(Item::StrayDoc { module: false })),
+        None => parse_doc(iter, true).map(|doc| doc.replace(Item::StrayDoc { module: true })),
+    };
+    if let Some(stray) = stray {
+        return stray;
+    }
+    let (span, token) = iter.peek().clone().split();
+    match token {
+        Token::Eof => (),
+        _ => {
+            iter.advance();
+            skip_item(iter);
+        },
+    }
+    unexpected(iter, span.replace(token), "item").map(Item::Error)
+}
+
+fn parse_import(iter: &mut TokenIterator) -> Span<Item> {
+    let start_span = match iter.advance().split() {
+        (span, Token::Import) => span,
+        (span, token) => return unexpected(iter, span.replace(token), "import").map(Item::Error)
+    };
+
+    let mut path = vec![];
+    let mut names = None;
+    match parse_import_path(iter, &mut path, &mut names) {
+        Ok(end_span) => Span::bridge(start_span, end_span, Item::Import {
+            path,
+            names,
+        }),
+        Err(err) => {
+            // skip the rest, along with the `}` closing a list of names
+            skip_statement(iter);
+            if names.is_some() {
+                if let Token::CurlyRight = *iter.peek().get_value() {
+                    iter.advance();
+                }
+            }
+            err.map(Item::Error)
         },
     }
 }
 
+/// Parses the `a.b.{c, d}` after `import`, giving where it ends. The list
+/// of names is started as soon as its `{` is seen.
+fn parse_import_path(
+    iter: &mut TokenIterator,
+    path: &mut Vec<Span<String>>,
+    names: &mut Option<Vec<Span<String>>>,
+) -> Result<Span<()>, Span<ParseError>> {
+    loop {
+        let name = expect_ident(iter, "module name")?;
+        let end_span = name.peek();
+        path.push(name);
+
+        if let (_span, Token::Dot) = iter.peek().clone().split() {
+            iter.advance();
+        }
+        else {
+            return Ok(end_span);
+        }
+
+        if let (_span, Token::CurlyLeft) = iter.peek().clone().split() {
+            iter.advance();
+            let list = names.get_or_insert_with(Vec::new);
+            loop {
+                list.push(expect_ident(iter, "ident")?);
+                match iter.peek().clone().split() {
+                    (_span, Token::Comma) => {
+                        iter.advance();
+                    },
+                    (span, Token::CurlyRight) => {
+                        iter.advance();
+                        return Ok(span);
+                    },
+                    (span, token) => return Err(unexpected(iter, span.replace(token), ", or }")),
+                }
+            }
+        }
+    }
+}
+
+/// Imports are only allowed at the top of the module tree, so they're
+/// handled here rather than in `parse_item`. A bracket a mistake left open
+/// in an earlier item isn't blamed for the file ending in this one.
+pub fn parse_module_item(iter: &mut TokenIterator) -> Span<Item> {
+    iter.unclosed.clear();
+    match *iter.peek().get_value() {
+        Token::Import => parse_import(iter),
+        _ => parse_item(iter),
+    }
+}
+
+/// Reads the `//!` comments at the top of a module.
+pub fn parse_module_doc(iter: &mut TokenIterator) -> Option<Span<String>> {
+    parse_doc(iter, true)
+}
+
 pub fn parse_module(name: &str, iter: &mut TokenIterator) -> Module {
+    let doc = parse_module_doc(iter);
     let mut items = vec![];
 
     loop {
-        match iter.peek().map(ToOwned::to_owned).unwrap().split() {
-            (_span, Token::Eof) => break,
-            _ => (),
+        skip_terminators(iter);
+        match *iter.peek().get_value() {
+            Token::Eof => break,
+            _ => items.push(parse_module_item(iter)),
         }
-
-        items.push(parse_item(iter));
     }
 
     Module {
         name: name.to_owned(),
-        items: items,
+        doc,
+        items,
+        lex_errors: iter.take_lex_errors(),
     }
 }
\ No newline at end of file
@@ -0,0 +1,188 @@
+use lexer::{Lexer, Token, LexError};
+use parser::{self, TokenIterator};
+use span::Span;
+use ast::{Statement, Item};
+use typecheck::{self, Bindings, Type};
+use interpreter::{Interpreter, Value};
+use diagnostic::{self, Diagnostic};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// What a successfully evaluated piece of input produced.
+pub enum Evaluated {
+    Value(Value, Type),
+    Let(String, Value, Type),
+    Item(String, Option<Type>),
+}
+
+impl fmt::Display for Evaluated {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Evaluated::Value(ref value, ref type_info) => write!(fmt, "{}: {}", value, type_info),
+            Evaluated::Let(ref name, ref value, ref type_info) =>
+                write!(fmt, "{}: {} = {}", name, type_info, value),
+            Evaluated::Item(ref name, Some(ref type_info)) => write!(fmt, "{}: {}", name, type_info),
+            Evaluated::Item(ref name, None) => write!(fmt, "{}: class", name),
+        }
+    }
+}
+
+enum Input {
+    Statement(Span<Statement>),
+    Item(Span<Item>),
+}
+
+/// An interactive session. Bindings and functions from earlier input stay
+/// in scope for later input.
+pub struct Repl {
+    lexer: Lexer,
+    functions: Bindings,
+    variables: Bindings,
+    interpreter: Interpreter,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            lexer: Lexer::new(),
+            functions: HashMap::new(),
+            variables: HashMap::new(),
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    /// Whether the input has unclosed brackets or comments, or stops where
+    /// a line break doesn't end a statement, meaning more lines should be
+    /// read before evaluating it.
+    pub fn needs_more_input(&self, source: &str) -> bool {
+        let mut depth = 0i32;
+        let mut last = None;
+        for token in self.lexer.tokens(Rc::new(source.to_owned())) {
+            match *token.get_value() {
+                Token::ParenLeft | Token::CurlyLeft => depth += 1,
+                Token::ParenRight | Token::CurlyRight => depth -= 1,
+                Token::Error(LexError::UnterminatedComment) => return true,
+                Token::Newline | Token::Eof => continue,
+                _ => (),
+            }
+            last = Some(token);
+        }
+        let unfinished = match last {
+            Some(ref token) => match *token.get_value() {
+                Token::Semicolon => false,
+                ref token => !token.ends_statement(),
+            },
+            None => false,
+        };
+        depth > 0 || unfinished
+    }
+
+    /// Splits the input into statements and items, along with the errors
+    /// from lexing it.
+    fn parse(&self, source: &str) -> (Vec<Input>, Vec<Span<LexError>>) {
+        let source = Rc::new(source.to_owned());
+        let end = Span::new((), source.len(), source.len(), source.clone());
+        let mut iter = TokenIterator::new(self.lexer.tokens(source), end);
+        let mut inputs = vec![];
+        loop {
+            match *iter.peek().get_value() {
+                Token::Newline | Token::Semicolon => {
+                    iter.advance();
+                },
+                Token::Eof => break,
+                Token::Fn | Token::Class | Token::Doc(_) | Token::ModuleDoc(_) => inputs.push(Input::Item(parser::parse_item(&mut iter))),
+                _ => {
+                    let start = iter.peek().range().0;
+                    inputs.push(Input::Statement(parser::parse_statement(&mut iter)));
+                    // a statement can stop at a stray `}` or `)` without
+                    // taking it, after reporting it
+                    if iter.peek().range().0 == start {
+                        iter.advance();
+                    }
+                },
+            }
+        }
+        (inputs, iter.take_lex_errors())
+    }
+
+    /// Parses, typechecks and evaluates the input. Nothing is evaluated if
+    /// any of it has errors.
+    pub fn eval(&mut self, source: &str) -> Result<Vec<Evaluated>, Vec<Diagnostic>> {
+        let mut functions = self.functions.clone();
+        let mut variables = self.variables.clone();
+        let mut checked = vec![];
+        let mut diagnostics = vec![];
+
+        let (inputs, lex_errors) = self.parse(source);
+        for input in inputs {
+            match input {
+                Input::Statement(stmt) => {
+                    let mut scope = functions.clone();
+                    scope.extend(variables.clone());
+                    let stmt = stmt.map(|stmt| typecheck::typecheck_statement(&scope, stmt));
+                    diagnostics.extend(diagnostic::statement_diagnostics(&stmt));
+                    if let Statement::Let { ref name, ref value } = *stmt.get_value() {
+                        if let Some(ref type_info) = value.get_value().type_info {
+                            variables.insert(name.get_value().clone(), type_info.clone());
+                        }
+                    }
+                    checked.push(Input::Statement(stmt));
+                },
+                Input::Item(item) => {
+                    if let Item::Function { ref name, .. } = *item.get_value() {
+                        let type_info = typecheck::function_type(item.get_value()).unwrap();
+                        functions.insert(name.get_value().clone(), type_info);
+                    }
+                    let item = item.map(|item| typecheck::typecheck_item(&functions, item));
+                    diagnostics.extend(diagnostic::item_diagnostics(&item));
+                    checked.push(Input::Item(item));
+                },
+            }
+        }
+
+        diagnostic::add_lex_errors(&mut diagnostics, &lex_errors);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(diagnostics);
+        }
+
+        self.functions = functions;
+        let mut results = vec![];
+        for input in checked {
+            match input {
+                Input::Statement(stmt) => {
+                    let value = self.interpreter.eval_statement(&stmt).map_err(|err| {
+                        let (span, err) = err.split();
+                        vec![Diagnostic::error(err.code(), Some(span), err)]
+                    })?;
+                    match *stmt.get_value() {
+                        Statement::Let { ref name, value: ref expr } => {
+                            let type_info = expr.get_value().type_info.clone().unwrap();
+                            self.variables.insert(name.get_value().clone(), type_info.clone());
+                            results.push(Evaluated::Let(name.get_value().clone(), value, type_info));
+                        },
+                        Statement::Expr(ref expr) => {
+                            results.push(Evaluated::Value(value, expr.type_info.clone().unwrap()));
+                        },
+                        _ => (),
+                    }
+                },
+                Input::Item(item) => {
+                    let name = match *item.get_value() {
+                        Item::Function { ref name, .. } | Item::Class { ref name, .. } => name.get_value().clone(),
+                        Item::Error(_) | Item::Import { .. } | Item::StrayDoc { .. } => continue,
+                    };
+                    results.push(Evaluated::Item(name, typecheck::function_type(item.get_value())));
+                    self.interpreter.define(item.value);
+                },
+            }
+        }
+        Ok(results)
+    }
+}
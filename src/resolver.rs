@@ -0,0 +1,208 @@
+use ast::{Item, Module, Program};
+use diagnostic::Diagnostic;
+use span::Span;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ImportError {
+    ModuleNotFound {
+        module: String,
+        file: String,
+    },
+    /// The modules importing each other, starting and ending with the same
+    /// one.
+    Cycle {
+        modules: Vec<String>,
+    },
+    UnknownItem {
+        module: String,
+        name: String,
+    },
+}
+
+impl ImportError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ImportError::ModuleNotFound { .. } => "E0601",
+            ImportError::Cycle { .. } => "E0602",
+            ImportError::UnknownItem { .. } => "E0603",
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImportError::ModuleNotFound { ref module, ref file } =>
+                write!(fmt, "couldn't find module `{}` (looked for {})", module, file),
+            ImportError::Cycle { ref modules } =>
+                write!(fmt, "import cycle: {}", modules.join(" -> ")),
+            ImportError::UnknownItem { ref module, ref name } =>
+                write!(fmt, "module `{}` has no item `{}`", module, name),
+        }
+    }
+}
+
+fn module_name(path: &[Span<String>]) -> String {
+    path.iter().map(|segment| segment.get_value().clone()).collect::<Vec<_>>().join(".")
+}
+
+/// The modules a module imports, with the span of each import path.
+fn imports(module: &Module) -> Vec<(String, Span<()>)> {
+    module.items.iter().filter_map(|item| match *item.get_value() {
+        Item::Import { ref path, .. } => Some((
+            module_name(path),
+            Span::bridge(path[0].peek(), path.last().unwrap().peek(), ()),
+        )),
+        _ => None,
+    }).collect()
+}
+
+/// The names a module's imports bring into scope, with the module and item
+/// each one refers to. Imports of modules or items that don't exist are
+/// skipped, since loading the program already reported them.
+pub fn imported_items<'a>(module: &Module, program: &'a Program) -> Vec<(String, &'a Module, &'a Item)> {
+    let mut out = vec![];
+    for item in &module.items {
+        if let Item::Import { ref path, ref names } = *item.get_value() {
+            let name = module_name(path);
+            let target = match program.module(&name) {
+                Some(target) => target,
+                None => continue,
+            };
+            match *names {
+                Some(ref names) => for imported in names {
+                    if let Some(found) = target.find_item(imported.get_value()) {
+                        out.push((imported.get_value().clone(), target, found.get_value()));
+                    }
+                },
+                None => for found in &target.items {
+                    if let Item::Function { name: ref item_name, .. } | Item::Class { name: ref item_name, .. } = *found.get_value() {
+                        out.push((format!("{}.{}", name, item_name.get_value()), target, found.get_value()));
+                    }
+                },
+            }
+        }
+    }
+    out
+}
+
+/// Loads the modules imported by a program. `import foo.bar` is looked for
+/// in `foo/bar.tl` under the root directory.
+pub struct Resolver<'a> {
+    root: PathBuf,
+    parse: &'a dyn Fn(&str, &str) -> Module,
+    /// Modules that are still having their imports loaded.
+    stack: Vec<String>,
+    program: Program,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(root: &Path, parse: &'a dyn Fn(&str, &str) -> Module) -> Resolver<'a> {
+        Resolver {
+            root: root.to_owned(),
+            parse,
+            stack: vec![],
+            program: Program {
+                modules: vec![],
+                files: HashMap::new(),
+            },
+            errors: vec![],
+        }
+    }
+
+    fn path_of(&self, module: &str) -> PathBuf {
+        let mut path = self.root.clone();
+        path.extend(module.split('.'));
+        path.set_extension("tl");
+        path
+    }
+
+    fn file_of(&self, module: &str) -> Option<&str> {
+        self.program.files.get(module).map(|file| &file[..])
+    }
+
+    fn error(&mut self, module: &str, span: Span<()>, err: ImportError) {
+        let mut diagnostic = Diagnostic::error(err.code(), Some(span), err);
+        if let Some(file) = self.file_of(module) {
+            diagnostic = diagnostic.in_file(file);
+        }
+        self.errors.push(diagnostic);
+    }
+
+    fn load(&mut self, module: Module) {
+        self.stack.push(module.name.clone());
+        for (name, span) in imports(&module) {
+            if let Some(start) = self.stack.iter().position(|loading| *loading == name) {
+                let mut modules = self.stack[start..].to_vec();
+                modules.push(name);
+                self.error(&module.name, span, ImportError::Cycle { modules });
+                continue;
+            }
+            if self.program.module(&name).is_some() {
+                continue;
+            }
+
+            let path = self.path_of(&name);
+            let file = path.display().to_string();
+            let mut source = String::new();
+            let read = File::open(&path).and_then(|mut file| file.read_to_string(&mut source));
+            if read.is_err() {
+                self.error(&module.name, span, ImportError::ModuleNotFound {
+                    module: name,
+                    file,
+                });
+                continue;
+            }
+            self.program.files.insert(name.clone(), file);
+            let imported = (self.parse)(&name, &source);
+            self.load(imported);
+        }
+        self.stack.pop();
+        self.program.modules.push(module);
+    }
+
+    /// Checks that every item named in an import exists.
+    fn check_names(&mut self) {
+        let mut errors = vec![];
+        for module in &self.program.modules {
+            for item in &module.items {
+                if let Item::Import { ref path, names: Some(ref names) } = *item.get_value() {
+                    let name = module_name(path);
+                    let target = match self.program.module(&name) {
+                        Some(target) => target,
+                        None => continue,
+                    };
+                    for imported in names {
+                        if target.find_item(imported.get_value()).is_none() {
+                            errors.push((module.name.clone(), imported.peek(), ImportError::UnknownItem {
+                                module: name.clone(),
+                                name: imported.get_value().clone(),
+                            }));
+                        }
+                    }
+                }
+            }
+        }
+        for (module, span, err) in errors {
+            self.error(&module, span, err);
+        }
+    }
+
+    /// Loads everything the entry module imports, directly or not.
+    pub fn resolve(mut self, entry: Module) -> Result<Program, Vec<Diagnostic>> {
+        self.load(entry);
+        self.check_names();
+        if self.errors.is_empty() {
+            Ok(self.program)
+        }
+        else {
+            Err(self.errors)
+        }
+    }
+}
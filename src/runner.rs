@@ -0,0 +1,200 @@
+use wasmi::{self, Caller, Engine, Extern, Linker, Store};
+use wasmi::core::{F64, Trap};
+use wasmi::errors::LinkerError;
+use ast::{Item, Module};
+use compiler::{self, HostFunction, HOST_MODULE};
+use interpreter::Value;
+use typecheck::Type;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RunError {
+    UnknownExport {
+        name: String,
+    },
+    ArgumentCount {
+        expected: usize,
+        given: usize,
+    },
+    InvalidArgument {
+        value: String,
+        expected: Type,
+    },
+    /// The engine rejected the module or trapped while running it.
+    Wasm(String),
+}
+
+impl RunError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            RunError::UnknownExport { .. } => "E0501",
+            RunError::ArgumentCount { .. } => "E0502",
+            RunError::InvalidArgument { .. } => "E0503",
+            RunError::Wasm(_) => "E0504",
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::UnknownExport { ref name } => write!(fmt, "no function is exported as `{}`", name),
+            RunError::ArgumentCount { expected, given } =>
+                write!(fmt, "function takes {} argument{} but was given {}", expected, if expected == 1 { "" } else { "s" }, given),
+            RunError::InvalidArgument { ref value, ref expected } =>
+                write!(fmt, "`{}` isn't a valid {}", value, expected),
+            RunError::Wasm(ref message) => write!(fmt, "{}", message),
+        }
+    }
+}
+
+/// The strings made while running, which compiled code refers to by their
+/// index.
+type Strings = Vec<String>;
+
+fn new_string(strings: &mut Strings, value: String) -> i32 {
+    strings.push(value);
+    (strings.len() - 1) as i32
+}
+
+fn get_string(strings: &Strings, handle: i32) -> Result<&str, Trap> {
+    strings.get(handle as usize)
+        .map(|string| &string[..])
+        .ok_or_else(|| Trap::new(format!("invalid string handle {}", handle)))
+}
+
+fn to_char(value: i32) -> Result<char, Trap> {
+    char::from_u32(value as u32).ok_or_else(|| Trap::new(format!("{} isn't a valid character", value)))
+}
+
+/// Defines the functions compiled code imports. Numbers are written out the
+/// same way the interpreter shows them.
+fn link_host_functions(linker: &mut Linker<Strings>) -> Result<(), LinkerError> {
+    linker.func_wrap(HOST_MODULE, HostFunction::StringFromMemory.name(), |mut caller: Caller<Strings>, offset: i32, len: i32| {
+        let memory = caller.get_export("memory")
+            .and_then(Extern::into_memory)
+            .ok_or_else(|| Trap::new("the module has no memory to read strings from"))?;
+        let text = memory.data(&caller)
+            .get(offset as usize..offset as usize + len as usize)
+            .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+            .ok_or_else(|| Trap::new("invalid string in memory"))?;
+        Ok(new_string(caller.data_mut(), text))
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::Concat.name(), |mut caller: Caller<Strings>, lhs: i32, rhs: i32| {
+        let text = format!("{}{}", get_string(caller.data(), lhs)?, get_string(caller.data(), rhs)?);
+        Ok(new_string(caller.data_mut(), text))
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::IntToString.name(), |mut caller: Caller<Strings>, value: i32| {
+        new_string(caller.data_mut(), Value::Integer(value).to_string())
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::LongToString.name(), |mut caller: Caller<Strings>, value: i64| {
+        new_string(caller.data_mut(), Value::Long(value).to_string())
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::RealToString.name(), |mut caller: Caller<Strings>, value: F64| {
+        new_string(caller.data_mut(), Value::Real(value.into()).to_string())
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::BoolToString.name(), |mut caller: Caller<Strings>, value: i32| {
+        new_string(caller.data_mut(), Value::Boolean(value != 0).to_string())
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::CharToString.name(), |mut caller: Caller<Strings>, value: i32| {
+        let ch = to_char(value)?;
+        Ok(new_string(caller.data_mut(), ch.to_string()))
+    })?;
+    linker.func_wrap(HOST_MODULE, HostFunction::CheckChar.name(), |value: i32| {
+        to_char(value).map(|_| value)
+    })?;
+    Ok(())
+}
+
+fn parse_arg(strings: &mut Strings, value: &str, type_info: &Type) -> Result<wasmi::Value, RunError> {
+    let invalid = || RunError::InvalidArgument {
+        value: value.to_owned(),
+        expected: type_info.clone(),
+    };
+    match *type_info {
+        Type::Integer => value.parse::<i32>().map(wasmi::Value::I32).map_err(|_| invalid()),
+        Type::Long => value.parse::<i64>().map(wasmi::Value::I64).map_err(|_| invalid()),
+        Type::Real => value.parse::<f64>().map(|value| wasmi::Value::F64(value.into())).map_err(|_| invalid()),
+        Type::Boolean => match value {
+            "true" => Ok(wasmi::Value::I32(1)),
+            "false" => Ok(wasmi::Value::I32(0)),
+            _ => Err(invalid()),
+        },
+        Type::String => Ok(wasmi::Value::I32(new_string(strings, value.to_owned()))),
+        Type::Char => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Ok(wasmi::Value::I32(u32::from(ch) as i32)),
+                _ => Err(invalid()),
+            }
+        },
+        _ => Err(invalid()),
+    }
+}
+
+fn to_value(strings: &Strings, value: &wasmi::Value, type_info: &Type) -> Value {
+    match (type_info, value) {
+        (&Type::Boolean, &wasmi::Value::I32(value)) => Value::Boolean(value != 0),
+        (&Type::String, &wasmi::Value::I32(handle)) =>
+            Value::String(strings.get(handle as usize).cloned().unwrap_or_default()),
+        (&Type::Char, &wasmi::Value::I32(value)) =>
+            Value::Char(char::from_u32(value as u32).unwrap_or(char::REPLACEMENT_CHARACTER)),
+        (_, &wasmi::Value::I32(value)) => Value::Integer(value),
+        (_, &wasmi::Value::I64(value)) => Value::Long(value),
+        (_, &wasmi::Value::F32(value)) => Value::Real(f32::from(value).into()),
+        (_, &wasmi::Value::F64(value)) => Value::Real(value.into()),
+        _ => Value::Void,
+    }
+}
+
+fn wasm_error<E: fmt::Display>(err: E) -> RunError {
+    RunError::Wasm(err.to_string())
+}
+
+/// Instantiates the code generated for a module and calls one of its
+/// exports. The arguments are parsed according to the types the function
+/// declares.
+pub fn run(module: &Module, code: &[u8], export: &str, args: &[String]) -> Result<Value, RunError> {
+    let exports = compiler::exported_functions(module);
+    let (arg_types, result) = exports.iter()
+        .find(|&(name, _)| name == export)
+        .and_then(|&(_, item)| match *item {
+            Item::Function { ref args, ref result, .. } => Some((
+                args.iter().map(|arg| arg.get_value().type_desc.get_value().clone()).collect::<Vec<_>>(),
+                result.get_value().clone(),
+            )),
+            _ => None,
+        })
+        .ok_or_else(|| RunError::UnknownExport {
+            name: export.to_owned(),
+        })?;
+
+    if arg_types.len() != args.len() {
+        return Err(RunError::ArgumentCount {
+            expected: arg_types.len(),
+            given: args.len(),
+        });
+    }
+    let mut strings = vec![];
+    let inputs = args.iter().zip(&arg_types)
+        .map(|(value, type_info)| parse_arg(&mut strings, value, type_info))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let engine = Engine::default();
+    let wasm_module = wasmi::Module::new(&engine, code).map_err(wasm_error)?;
+    let mut store = Store::new(&engine, strings);
+    let mut linker = <Linker<Strings>>::new(&engine);
+    link_host_functions(&mut linker).map_err(wasm_error)?;
+    let instance = linker.instantiate(&mut store, &wasm_module)
+        .and_then(|pre| pre.start(&mut store))
+        .map_err(wasm_error)?;
+    let func = instance.get_func(&store, export).ok_or_else(|| RunError::UnknownExport {
+        name: export.to_owned(),
+    })?;
+
+    let mut outputs: Vec<wasmi::Value> = func.ty(&store).results().iter()
+        .map(|ty| wasmi::Value::default(*ty))
+        .collect();
+    func.call(&mut store, &inputs, &mut outputs).map_err(wasm_error)?;
+    Ok(outputs.first().map(|value| to_value(store.data(), value, &result)).unwrap_or(Value::Void))
+}
@@ -0,0 +1,139 @@
+use lexer::{Lexer, Tokens};
+use span::Span;
+use ast::{Module, Program};
+use diagnostic::{self, Diagnostic, IO_ERROR};
+use wasm::{Dump, Module as WasmModule};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use interpreter::Value;
+use parser::{self, TokenIterator};
+use typecheck;
+use compiler;
+use lint;
+use runner;
+use resolver::Resolver;
+
+/// Runs sources through the compiler pipeline. Each stage is exposed on
+/// its own so tools can stop wherever they need to.
+pub struct Session {
+    lexer: Lexer,
+    /// The directory imported modules are looked up in.
+    root: PathBuf,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::with_root(".")
+    }
+
+    pub fn with_root<P: AsRef<Path>>(root: P) -> Session {
+        Session {
+            lexer: Lexer::new(),
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    pub fn lex(&self, source: Rc<String>) -> Tokens {
+        self.lexer.tokens(source)
+    }
+
+    pub fn parse(&self, name: &str, source: &str) -> Module {
+        let source = Rc::new(source.to_owned());
+        let end = Span::new((), source.len(), source.len(), source.clone());
+        parser::parse_module(name, &mut TokenIterator::new(self.lex(source), end))
+    }
+
+    /// Parses a source along with every module it imports.
+    pub fn load(&self, name: &str, source: &str) -> Result<Program, Vec<Diagnostic>> {
+        let parse = |name: &str, source: &str| self.parse(name, source);
+        Resolver::new(&self.root, &parse).resolve(self.parse(name, source))
+    }
+
+    pub fn typecheck(&self, program: Program) -> Program {
+        typecheck::typecheck_program(program)
+    }
+
+    /// Loads and typechecks a source, returning the typed program only if
+    /// it has no errors. Warnings are only included along with errors; use
+    /// `lint` to get them for a program that passed.
+    pub fn check(&self, name: &str, source: &str) -> Result<Program, Vec<Diagnostic>> {
+        let program = self.typecheck(self.load(name, source)?);
+        let mut diagnostics = diagnostic::collect_program(&program);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            diagnostics.extend(self.lint(&program));
+            Err(diagnostics)
+        }
+        else {
+            Ok(program)
+        }
+    }
+
+    /// Warns about code that's valid but probably not what was meant.
+    pub fn lint(&self, program: &Program) -> Vec<Diagnostic> {
+        lint::lint_program(program)
+    }
+
+    /// Generates code for a typed program that passed `check`.
+    pub fn generate(&self, program: &Program) -> Result<WasmModule, Vec<Diagnostic>> {
+        compiler::compile_program(program).map_err(|errors| {
+            errors.into_iter().map(|(module, err)| {
+                let (span, err) = err.split();
+                let diagnostic = Diagnostic::error(err.code(), Some(span), err);
+                match program.files.get(&module) {
+                    Some(file) => diagnostic.in_file(file),
+                    None => diagnostic,
+                }
+            }).collect()
+        })
+    }
+
+    /// Generates the binary encoding of a program that passed `check`.
+    pub fn assemble(&self, program: &Program) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let mut code = vec![];
+        self.generate(program)?.dump(&mut code);
+        Ok(code)
+    }
+
+    pub fn compile_source(&self, name: &str, source: &str) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let program = self.check(name, source)?;
+        self.assemble(&program)
+    }
+
+    /// Compiles a source and calls one of its exports with the given
+    /// arguments, returning what it gives back.
+    pub fn run(&self, name: &str, source: &str, export: &str, args: &[String]) -> Result<Value, Vec<Diagnostic>> {
+        let program = self.check(name, source)?;
+        self.execute(&program, export, args)
+    }
+
+    /// Calls one of the exports of a program that passed `check`.
+    pub fn execute(&self, program: &Program, export: &str, args: &[String]) -> Result<Value, Vec<Diagnostic>> {
+        let code = self.assemble(program)?;
+        runner::run(program.entry(), &code, export, args)
+            .map_err(|err| vec![Diagnostic::error(err.code(), None, err)])
+    }
+
+    /// Compiles a file, using its stem as the module name.
+    pub fn compile_path<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        let file = path.display().to_string();
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| vec![Diagnostic::error(IO_ERROR, None, format!("couldn't read {}: {}", file, err))])?;
+        let name = path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| file.clone());
+        self.compile_source(&name, &source).map_err(|diagnostics| {
+            diagnostics.into_iter().map(|diagnostic| diagnostic.in_file(&file)).collect()
+        })
+    }
+}
@@ -1,6 +1,13 @@
 use std::rc::Rc;
 use std::fmt;
 
+/// A 1-based line and column, with columns counted in characters.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
 #[derive(Clone)]
 pub struct Span<T> {
     pub value: T,
@@ -12,16 +19,16 @@ pub struct Span<T> {
 impl<T> Span<T> {
     pub fn new(value: T, first: usize, last: usize, buffer: Rc<String>) -> Span<T> {
         Span {
-            value: value,
-            first: first,
-            last: last,
-            buffer: buffer,
+            value,
+            first,
+            last,
+            buffer,
         }
     }
 
     pub fn replace<U>(&self, value: U) -> Span<U> {
         Span {
-            value: value,
+            value,
             first: self.first,
             last: self.last,
             buffer: self.buffer.clone(),
@@ -39,7 +46,7 @@ impl<T> Span<T> {
 
     pub fn bridge<U, V>(first: Span<U>, last: Span<V>, value: T) -> Span<T> {
         Span {
-            value: value,
+            value,
             first: first.first,
             last: last.last,
             buffer: first.buffer.clone(),
@@ -55,9 +62,54 @@ impl<T> Span<T> {
         }
     }
 
+    /// Part of this span, from byte offsets relative to its start.
+    pub fn subspan(&self, start: usize, end: usize) -> Span<()> {
+        Span {
+            value: (),
+            first: self.first + start,
+            last: self.first + end,
+            buffer: self.buffer.clone(),
+        }
+    }
+
     pub fn get_value(&self) -> &T {
         &self.value
     }
+
+    /// Points the span at an edited copy of its buffer, in which its text
+    /// has moved by `delta` bytes.
+    pub fn relocate(&mut self, buffer: &Rc<String>, delta: isize) {
+        self.first = (self.first as isize + delta) as usize;
+        self.last = (self.last as isize + delta) as usize;
+        self.buffer = buffer.clone();
+    }
+
+    /// The source text the span covers.
+    pub fn text(&self) -> &str {
+        &self.buffer[self.first..self.last]
+    }
+
+    /// The byte offsets of the start and end of the span.
+    pub fn range(&self) -> (usize, usize) {
+        (self.first, self.last)
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let before = &self.buffer[..offset];
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+        Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    pub fn start(&self) -> Position {
+        self.position(self.first)
+    }
+
+    pub fn end(&self) -> Position {
+        self.position(self.last)
+    }
 }
 
 impl<T: fmt::Display> fmt::Display for Span<T> {
@@ -66,23 +118,36 @@ impl<T: fmt::Display> fmt::Display for Span<T> {
         let first_line_start = self.buffer[..self.first].rfind('\n').map(|index| index + 1).unwrap_or(0);
         let last_line_end = self.buffer[self.last..].find('\n').map(|index| index + self.last).unwrap_or(self.buffer.len());
         let mut line_start = first_line_start;
-        for line in self.buffer[first_line_start..last_line_end].lines() {
+        for line in self.buffer[first_line_start..last_line_end].split('\n') {
+            let next_line_start = line_start + line.len() + 1;
+            // a `\r` before the `\n` isn't shown
+            let line = line.strip_suffix('\r').unwrap_or(line);
             writeln!(fmt, "--> {}", line)?;
-            let first = if self.first > line_start { self.first - line_start } else { 0 };
-            let last = (self.last - line_start).min(line.len());
+            let first = char_boundary(line, self.first.saturating_sub(line_start));
+            let last = char_boundary(line, self.last.saturating_sub(line_start)).max(first);
+            // lined up by characters rather than bytes, like `Position`
             writeln!(
                 fmt,
                 "    {: >spaces$}{:^>carets$}",
                 "", "",
-                spaces = first,
-                carets = if last > first { last - first } else { 0 },
+                spaces = line[..first].chars().count(),
+                carets = line[first..last].chars().count(),
             )?;
-            line_start += line.len() + 1;
+            line_start = next_line_start;
         }
         Ok(())
     }
 }
 
+/// The closest char boundary in `text` at or before `index`.
+fn char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
 impl<T: fmt::Debug> fmt::Debug for Span<T> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         self.value.fmt(fmt)?;
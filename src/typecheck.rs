@@ -1,6 +1,8 @@
 use std::collections::HashMap;
+use std::fmt;
 use span::Span;
-use ast::{Expr, BinOp, Statement, Item, Module};
+use ast::{Expr, Fragment, BinOp, Statement, Item, Module, Program};
+use resolver;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum TypeError {
@@ -32,15 +34,101 @@ pub enum TypeError {
     UndefinedVariable {
         name: String,
     },
+    /// A hole in an interpolated string with a value that can't be written
+    /// out as text.
+    NotDisplayable {
+        given: Box<Type>,
+    },
+    InvalidCast {
+        from: Box<Type>,
+        to: Box<Type>,
+    },
+}
+
+impl TypeError {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            TypeError::ParseError => "E0200",
+            TypeError::BinOpMismatch { .. } => "E0201",
+            TypeError::FunctionArgsMismatch { .. } => "E0202",
+            TypeError::CallingNonFunction { .. } => "E0203",
+            TypeError::ExpectedType { .. } => "E0204",
+            TypeError::IfBranchMismatch { .. } => "E0205",
+            TypeError::UndefinedVariable { .. } => "E0206",
+            TypeError::NotDisplayable { .. } => "E0207",
+            TypeError::InvalidCast { .. } => "E0208",
+        }
+    }
+
+    /// Whether this error was caused by another error further down the
+    /// tree, which will already have been reported on its own.
+    pub fn is_cascade(&self) -> bool {
+        match *self {
+            TypeError::ParseError => true,
+            TypeError::BinOpMismatch { ref lhs, ref rhs, .. } => lhs.is_error() || rhs.is_error(),
+            TypeError::FunctionArgsMismatch { ref func_args, ref given_args } =>
+                func_args.iter().chain(given_args).any(Type::is_error),
+            TypeError::CallingNonFunction { ref given_type } => given_type.is_error(),
+            TypeError::ExpectedType { ref expected, ref given } => expected.is_error() || given.is_error(),
+            TypeError::IfBranchMismatch { ref branch_then, ref branch_else } =>
+                branch_then.is_error() || branch_else.is_error(),
+            TypeError::UndefinedVariable { .. } => false,
+            TypeError::NotDisplayable { ref given } => given.is_error(),
+            TypeError::InvalidCast { ref from, ref to } => from.is_error() || to.is_error(),
+        }
+    }
+}
+
+fn write_types(fmt: &mut fmt::Formatter, types: &[Type]) -> fmt::Result {
+    for (index, type_info) in types.iter().enumerate() {
+        if index > 0 {
+            write!(fmt, ", ")?;
+        }
+        write!(fmt, "{}", type_info)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypeError::ParseError => write!(fmt, "syntax error"),
+            TypeError::BinOpMismatch { ref op, ref lhs, ref rhs } =>
+                write!(fmt, "cannot apply `{}` to {} and {}", op, lhs, rhs),
+            TypeError::FunctionArgsMismatch { ref func_args, ref given_args } => {
+                write!(fmt, "function takes (")?;
+                write_types(fmt, func_args)?;
+                write!(fmt, ") but was given (")?;
+                write_types(fmt, given_args)?;
+                write!(fmt, ")")
+            },
+            TypeError::CallingNonFunction { ref given_type } =>
+                write!(fmt, "cannot call a value of type {}", given_type),
+            TypeError::ExpectedType { ref expected, ref given } =>
+                write!(fmt, "expected {}, found {}", expected, given),
+            TypeError::IfBranchMismatch { ref branch_then, ref branch_else } =>
+                write!(fmt, "if branches have different types: {} and {}", branch_then, branch_else),
+            TypeError::UndefinedVariable { ref name } =>
+                write!(fmt, "undefined variable `{}`", name),
+            TypeError::NotDisplayable { ref given } =>
+                write!(fmt, "cannot put a value of type {} in a string", given),
+            TypeError::InvalidCast { ref from, ref to } =>
+                write!(fmt, "cannot convert {} to {}", from, to),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Type {
     Error(TypeError),
     Integer,
+    /// A 64-bit integer, written `i64`.
+    Long,
     Real,
     String,
     Boolean,
+    /// A Unicode scalar value.
+    Char,
     Void,
 
     Function {
@@ -49,6 +137,38 @@ pub enum Type {
     }
 }
 
+impl Type {
+    pub fn is_error(&self) -> bool {
+        matches!(*self, Type::Error(_))
+    }
+
+    /// Whether values of this type can be written out in an interpolated
+    /// string.
+    pub fn is_displayable(&self) -> bool {
+        matches!(*self, Type::Integer | Type::Long | Type::Real | Type::String | Type::Boolean | Type::Char)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Error(_) => write!(fmt, "{{error}}"),
+            Type::Integer => write!(fmt, "int"),
+            Type::Long => write!(fmt, "i64"),
+            Type::Real => write!(fmt, "real"),
+            Type::String => write!(fmt, "string"),
+            Type::Boolean => write!(fmt, "bool"),
+            Type::Char => write!(fmt, "char"),
+            Type::Void => write!(fmt, "void"),
+            Type::Function { ref result, ref args } => {
+                write!(fmt, "fn(")?;
+                write_types(fmt, args)?;
+                write!(fmt, ") -> {}", result)
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Typed<T> {
     pub value: T,
@@ -58,14 +178,14 @@ pub struct Typed<T> {
 impl<T> Typed<T> {
     pub fn new(value: T) -> Typed<T> {
         Typed {
-            value: value,
+            value,
             type_info: None,
         }
     }
 
     pub fn with_type(value: T, type_info: Type) -> Typed<T> {
         Typed {
-            value: value,
+            value,
             type_info: Some(type_info),
         }
     }
@@ -80,8 +200,30 @@ fn typecheck_expr(bindings: &Bindings, expr: Typed<Expr>) -> Typed<Expr> {
     match expr.value {
         expr @ Expr::Error(_) => Typed::with_type(expr, Type::Error(TypeError::ParseError)),
         expr @ Expr::ConstInteger(_) => Typed::with_type(expr, Type::Integer),
+        expr @ Expr::ConstLong(_) => Typed::with_type(expr, Type::Long),
         expr @ Expr::ConstNumber(_) => Typed::with_type(expr, Type::Real),
         expr @ Expr::ConstString(_) => Typed::with_type(expr, Type::String),
+        expr @ Expr::ConstChar(_) => Typed::with_type(expr, Type::Char),
+        Expr::Interpolated(fragments) => {
+            let fragments: Vec<Fragment> = fragments.into_iter().map(|fragment| match fragment {
+                Fragment::Hole(expr) => Fragment::Hole(expr.map(|expr| typecheck_expr(bindings, expr))),
+                text => text,
+            }).collect();
+
+            let undisplayable = fragments.iter()
+                .filter_map(|fragment| match *fragment {
+                    Fragment::Hole(ref expr) => expr.get_value().type_info.clone(),
+                    Fragment::Text(_) => None,
+                })
+                .find(|type_info| !type_info.is_displayable());
+            let type_info = match undisplayable {
+                Some(given) => Type::Error(TypeError::NotDisplayable {
+                    given: Box::new(given),
+                }),
+                None => Type::String,
+            };
+            Typed::with_type(Expr::Interpolated(fragments), type_info)
+        },
         Expr::Variable(name) => {
             if let Some(type_info) = bindings.get(&name) {
                 Typed::with_type(Expr::Variable(name), type_info.clone())
@@ -101,6 +243,7 @@ fn typecheck_expr(bindings: &Bindings, expr: Typed<Expr>) -> Typed<Expr> {
             let right_ty = rhs.get_value().type_info.clone().unwrap();
             let type_info = match (&left_ty, &right_ty) {
                 (Type::Integer, Type::Integer) => Type::Integer,
+                (Type::Long, Type::Long) => Type::Long,
                 (Type::Real, Type::Real) => Type::Real,
                 _ => Type::Error(TypeError::BinOpMismatch {
                     op: op.clone(),
@@ -166,14 +309,34 @@ fn typecheck_expr(bindings: &Bindings, expr: Typed<Expr>) -> Typed<Expr> {
 
             Typed::with_type(Expr::If {
                 condition: Box::new(condition),
-                branch_then: branch_then,
-                branch_else: branch_else,
+                branch_then,
+                branch_else,
+            }, type_info)
+        },
+
+        Expr::Cast { value, to } => {
+            let value = value.map(|expr| typecheck_expr(bindings, expr));
+            let from = value.get_value().type_info.clone().unwrap();
+            // chars convert to and from their code point, and ints to and
+            // from i64s
+            let type_info = match (&from, to.get_value()) {
+                (from, to) if from == to => to.clone(),
+                (&Type::Char, &Type::Integer) | (&Type::Integer, &Type::Char) |
+                (&Type::Integer, &Type::Long) | (&Type::Long, &Type::Integer) => to.get_value().clone(),
+                _ => Type::Error(TypeError::InvalidCast {
+                    from: Box::new(from.clone()),
+                    to: Box::new(to.get_value().clone()),
+                }),
+            };
+            Typed::with_type(Expr::Cast {
+                value: Box::new(value),
+                to,
             }, type_info)
         },
     }
 }
 
-fn typecheck_statement(bindings: &Bindings, stmt: Statement) -> Statement {
+pub fn typecheck_statement(bindings: &Bindings, stmt: Statement) -> Statement {
     match stmt {
         Statement::Expr(expr) => Statement::Expr(typecheck_expr(bindings, expr)),
         Statement::Let { name, value } => Statement::Let {
@@ -184,12 +347,24 @@ fn typecheck_statement(bindings: &Bindings, stmt: Statement) -> Statement {
     }
 }
 
-type Bindings = HashMap<String, Type>;
+pub type Bindings = HashMap<String, Type>;
 
-pub fn typecheck_item(item: Item) -> Item {
+/// The type of a function item, as seen by its callers.
+pub fn function_type(item: &Item) -> Option<Type> {
+    match *item {
+        Item::Function { ref args, ref result, .. } => Some(Type::Function {
+            result: Box::new(result.get_value().clone()),
+            args: args.iter().map(|arg| arg.get_value().type_desc.get_value().clone()).collect(),
+        }),
+        _ => None,
+    }
+}
+
+/// Typechecks an item. Function bodies can see everything in `scope`.
+pub fn typecheck_item(scope: &Bindings, item: Item) -> Item {
     match item {
-        Item::Function { name, args, body, result } => {
-            let mut bindings: Bindings = HashMap::new();
+        Item::Function { doc, name, args, body, result } => {
+            let mut bindings = scope.clone();
             for arg in &args {
                 bindings.insert(
                     arg.get_value().name.get_value().clone(),
@@ -200,14 +375,11 @@ pub fn typecheck_item(item: Item) -> Item {
 
             for statement in body {
                 let statement = statement.map(|stmt| typecheck_statement(&bindings, stmt));
-                match statement.get_value() {
-                    Statement::Let { ref name, ref value } => {
-                        bindings.insert(
-                            name.get_value().clone(),
-                            value.get_value().type_info.clone().unwrap()
-                        );
-                    }
-                    _ => (),
+                if let Statement::Let { ref name, ref value } = statement.get_value() {
+                    bindings.insert(
+                        name.get_value().clone(),
+                        value.get_value().type_info.clone().unwrap()
+                    );
                 }
                 result_body.push(statement);
             }
@@ -222,22 +394,48 @@ pub fn typecheck_item(item: Item) -> Item {
             }
 
             Item::Function {
-                name, args,
+                doc, name, args,
                 result,
                 body: result_body,
             }
         },
-        Item::Class { name, members } => Item::Class {
-            name,
-            members: members.into_iter().map(|span| span.map(typecheck_item)).collect(),
+        Item::Class { doc, name, members } => Item::Class {
+            doc, name,
+            members: members.into_iter().map(|span| span.map(|item| typecheck_item(scope, item))).collect(),
         },
         item => item,
     }
 }
 
-pub fn typecheck_module(module: Module) -> Module {
+/// Typechecks a module. `imported` holds the types of the functions its
+/// imports bring into scope, which its own functions shadow.
+pub fn typecheck_module(module: Module, imported: &Bindings) -> Module {
+    let mut scope = imported.clone();
+    for item in &module.items {
+        if let Item::Function { ref name, .. } = *item.get_value() {
+            scope.insert(name.get_value().clone(), function_type(item.get_value()).unwrap());
+        }
+    }
+
     Module {
         name: module.name,
-        items: module.items.into_iter().map(|span| span.map(typecheck_item)).collect(),
+        doc: module.doc,
+        items: module.items.into_iter().map(|span| span.map(|item| typecheck_item(&scope, item))).collect(),
+        lex_errors: module.lex_errors,
+    }
+}
+
+pub fn typecheck_program(program: Program) -> Program {
+    let scopes: Vec<Bindings> = program.modules.iter().map(|module| {
+        resolver::imported_items(module, &program).into_iter()
+            .filter_map(|(name, _, item)| function_type(item).map(|type_info| (name, type_info)))
+            .collect()
+    }).collect();
+
+    Program {
+        modules: program.modules.into_iter().zip(&scopes)
+            .map(|(module, scope)| typecheck_module(module, scope))
+            .collect(),
+        files: program.files,
     }
 }
@@ -0,0 +1,341 @@
+use wasm::{
+    Module, Op, ValueType, BlockType, ExportKind, ImportKind, ResizableLimits, GlobalType,
+    MemoryImmediate, Code,
+};
+use std::fmt::Write;
+
+fn value_type(ty: &ValueType) -> &'static str {
+    match *ty {
+        ValueType::I32 => "i32",
+        ValueType::I64 => "i64",
+        ValueType::F32 => "f32",
+        ValueType::F64 => "f64",
+    }
+}
+
+fn block_type(ty: &BlockType) -> String {
+    match ty.0 {
+        Some(ref ty) => format!(" (result {})", value_type(ty)),
+        None => String::new(),
+    }
+}
+
+fn limits(limits: &ResizableLimits) -> String {
+    match limits.maximum {
+        Some(maximum) => format!("{} {}", limits.initial, maximum),
+        None => format!("{}", limits.initial),
+    }
+}
+
+fn global_type(ty: &GlobalType) -> String {
+    if ty.mutable {
+        format!("(mut {})", value_type(&ty.content))
+    }
+    else {
+        value_type(&ty.content).to_owned()
+    }
+}
+
+fn memory_op(name: &str, imm: &MemoryImmediate) -> String {
+    format!("{} offset={} align={}", name, imm.offset, 1u32 << imm.flags)
+}
+
+/// Instructions that take no immediates, by their text format name.
+fn plain_op(op: &Op) -> Option<&'static str> {
+    use wasm::Op::*;
+    let name = match *op {
+        Unreachable => "unreachable",
+        Nop => "nop",
+        Else => "else",
+        End => "end",
+        Return => "return",
+        Drop => "drop",
+        Select => "select",
+        I32Eqz => "i32.eqz",
+        I32Eq => "i32.eq",
+        I32NE => "i32.ne",
+        I32LtS => "i32.lt_s",
+        I32LtU => "i32.lt_u",
+        I32GtS => "i32.gt_s",
+        I32GtU => "i32.gt_u",
+        I32LeS => "i32.le_s",
+        I32LeU => "i32.le_u",
+        I32GeS => "i32.ge_s",
+        I32GeU => "i32.ge_u",
+        I64Eqz => "i64.eqz",
+        I64Eq => "i64.eq",
+        I64Ne => "i64.ne",
+        I64LtS => "i64.lt_s",
+        I64LtU => "i64.lt_u",
+        I64GtS => "i64.gt_s",
+        I64GtU => "i64.gt_u",
+        I64LeS => "i64.le_s",
+        I64LeU => "i64.le_u",
+        I64GeS => "i64.ge_s",
+        I64GeU => "i64.ge_u",
+        F32Eq => "f32.eq",
+        F32Ne => "f32.ne",
+        F32Lt => "f32.lt",
+        F32Gt => "f32.gt",
+        F32Le => "f32.le",
+        F32Ge => "f32.ge",
+        F64Eq => "f64.eq",
+        F64Ne => "f64.ne",
+        F64Lt => "f64.lt",
+        F64Gt => "f64.gt",
+        F64Le => "f64.le",
+        F64Ge => "f64.ge",
+        I32Clz => "i32.clz",
+        I32Ctz => "i32.ctz",
+        I32Popcnt => "i32.popcnt",
+        I32Add => "i32.add",
+        I32Sub => "i32.sub",
+        I32Mul => "i32.mul",
+        I32DivS => "i32.div_s",
+        I32DivU => "i32.div_u",
+        I32RemS => "i32.rem_s",
+        I32RemU => "i32.rem_u",
+        I32And => "i32.and",
+        I32Or => "i32.or",
+        I32Xor => "i32.xor",
+        I32Shl => "i32.shl",
+        I32ShrS => "i32.shr_s",
+        I32ShrU => "i32.shr_u",
+        I32Rotl => "i32.rotl",
+        I32Rotr => "i32.rotr",
+        I64Clz => "i64.clz",
+        I64Ctz => "i64.ctz",
+        I64Popcnt => "i64.popcnt",
+        I64Add => "i64.add",
+        I64Sub => "i64.sub",
+        I64Mul => "i64.mul",
+        I64DivS => "i64.div_s",
+        I64DivU => "i64.div_u",
+        I64RemS => "i64.rem_s",
+        I64RemU => "i64.rem_u",
+        I64And => "i64.and",
+        I64Or => "i64.or",
+        I64Xor => "i64.xor",
+        I64Shl => "i64.shl",
+        I64ShrS => "i64.shr_s",
+        I64ShrU => "i64.shr_u",
+        I64Rotl => "i64.rotl",
+        I64Rotr => "i64.rotr",
+        F32Abs => "f32.abs",
+        F32Neg => "f32.neg",
+        F32Ceil => "f32.ceil",
+        F32Floor => "f32.floor",
+        F32Trunc => "f32.trunc",
+        F32Nearest => "f32.nearest",
+        F32Sqrt => "f32.sqrt",
+        F32Add => "f32.add",
+        F32Sub => "f32.sub",
+        F32Mul => "f32.mul",
+        F32Div => "f32.div",
+        F32Min => "f32.min",
+        F32Max => "f32.max",
+        F32Copysign => "f32.copysign",
+        F64Abs => "f64.abs",
+        F64Neg => "f64.neg",
+        F64Ceil => "f64.ceil",
+        F64Floor => "f64.floor",
+        F64Trunc => "f64.trunc",
+        F64Nearest => "f64.nearest",
+        F64Sqrt => "f64.sqrt",
+        F64Add => "f64.add",
+        F64Sub => "f64.sub",
+        F64Mul => "f64.mul",
+        F64Div => "f64.div",
+        F64Min => "f64.min",
+        F64Max => "f64.max",
+        F64Copysign => "f64.copysign",
+        I32wrapI64 => "i32.wrap_i64",
+        I32TruncSF32 => "i32.trunc_f32_s",
+        I32TruncUF32 => "i32.trunc_f32_u",
+        I32TruncSF64 => "i32.trunc_f64_s",
+        I32TruncUF64 => "i32.trunc_f64_u",
+        I64ExtendSI32 => "i64.extend_i32_s",
+        I64ExtendUI32 => "i64.extend_i32_u",
+        I64TruncSF32 => "i64.trunc_f32_s",
+        I64TruncUF32 => "i64.trunc_f32_u",
+        I64TruncSF64 => "i64.trunc_f64_s",
+        I64TruncUF64 => "i64.trunc_f64_u",
+        F32ConvertSI32 => "f32.convert_i32_s",
+        F32ConvertUI32 => "f32.convert_i32_u",
+        F32ConvertSI64 => "f32.convert_i64_s",
+        F32ConvertUI64 => "f32.convert_i64_u",
+        F32DemoteF64 => "f32.demote_f64",
+        F64ConvertSI32 => "f64.convert_i32_s",
+        F64ConvertUI32 => "f64.convert_i32_u",
+        F64ConvertSI64 => "f64.convert_i64_s",
+        F64ConvertUI64 => "f64.convert_i64_u",
+        F64PromoteF32 => "f64.promote_f32",
+        I32ReinterpretF32 => "i32.reinterpret_f32",
+        I64ReinterpretF64 => "i64.reinterpret_f64",
+        F32ReinterpretI32 => "f32.reinterpret_i32",
+        F64ReinterpretI64 => "f64.reinterpret_i64",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn format_op(op: &Op) -> String {
+    use wasm::Op::*;
+    if let Some(name) = plain_op(op) {
+        return name.to_owned();
+    }
+    match *op {
+        Block { ref sig } => format!("block{}", block_type(sig)),
+        Loop { ref sig } => format!("loop{}", block_type(sig)),
+        If => "if".to_owned(),
+        Br { depth } => format!("br {}", depth),
+        BrIf { depth } => format!("br_if {}", depth),
+        BrTable(ref target) => {
+            let mut text = "br_table".to_owned();
+            for depth in &target.table {
+                write!(text, " {}", depth).unwrap();
+            }
+            write!(text, " {}", target.default_target).unwrap();
+            text
+        },
+        Call { ref index } => format!("call {}", **index),
+        CallIndirect { index, .. } => format!("call_indirect (type {})", index),
+        GetLocal(ref index) => format!("local.get {}", **index),
+        SetLocal(ref index) => format!("local.set {}", **index),
+        TeeLocal(ref index) => format!("local.tee {}", **index),
+        GetGlobal(ref index) => format!("global.get {}", **index),
+        SetGlobal(ref index) => format!("global.set {}", **index),
+        I32Load { ref imm } => memory_op("i32.load", imm),
+        I64Load { ref imm } => memory_op("i64.load", imm),
+        F32Load { ref imm } => memory_op("f32.load", imm),
+        F64Load { ref imm } => memory_op("f64.load", imm),
+        I32Load8S { ref imm } => memory_op("i32.load8_s", imm),
+        I32Load8U { ref imm } => memory_op("i32.load8_u", imm),
+        I32Load16S { ref imm } => memory_op("i32.load16_s", imm),
+        I32Load16U { ref imm } => memory_op("i32.load16_u", imm),
+        I64Load8S { ref imm } => memory_op("i64.load8_s", imm),
+        I64Load8U { ref imm } => memory_op("i64.load8_u", imm),
+        I64Load16S { ref imm } => memory_op("i64.load16_s", imm),
+        I64Load16U { ref imm } => memory_op("i64.load16_u", imm),
+        I64load32S { ref imm } => memory_op("i64.load32_s", imm),
+        I64load32U { ref imm } => memory_op("i64.load32_u", imm),
+        I32Store { ref imm } => memory_op("i32.store", imm),
+        I64Store { ref imm } => memory_op("i64.store", imm),
+        F32Store { ref imm } => memory_op("f32.store", imm),
+        F64Store { ref imm } => memory_op("f64.store", imm),
+        I32Store8 { ref imm } => memory_op("i32.store8", imm),
+        I32Store16 { ref imm } => memory_op("i32.store16", imm),
+        I64Store8 { ref imm } => memory_op("i64.store8", imm),
+        I64Store16 { ref imm } => memory_op("i64.store16", imm),
+        I64Store32 { ref imm } => memory_op("i64.store32", imm),
+        CurrentMemory { .. } => "memory.size".to_owned(),
+        GrowMemory { .. } => "memory.grow".to_owned(),
+        I32Const(value) => format!("i32.const {}", value),
+        I64Const(value) => format!("i64.const {}", value),
+        F32Const(value) => format!("f32.const {:?}", value),
+        F64Const(value) => format!("f64.const {:?}", value),
+        ref op => unreachable!("{:?} has no immediates", op),
+    }
+}
+
+fn write_code(out: &mut String, depth: usize, code: &Code) {
+    let mut depth = depth;
+    for op in &code.0 {
+        match *op {
+            Op::End | Op::Else => depth -= 1,
+            _ => (),
+        }
+        writeln!(out, "{:indent$}{}", "", format_op(op), indent = depth * 2).unwrap();
+        match *op {
+            Op::Block { .. } | Op::Loop { .. } | Op::If | Op::Else => depth += 1,
+            _ => (),
+        }
+    }
+}
+
+fn inline_code(code: &Code) -> String {
+    let ops: Vec<String> = code.0.iter().map(format_op).collect();
+    ops.join(" ")
+}
+
+/// Renders a module in the WebAssembly text format, using flat
+/// instructions and `(;index;)` comments in place of symbolic names.
+pub fn print_module(module: &Module) -> String {
+    let mut out = "(module\n".to_owned();
+
+    for (index, ty) in module.types.iter().flat_map(|types| types.iter()).enumerate() {
+        write!(out, "  (type (;{};) (func", index).unwrap();
+        if !ty.params.is_empty() {
+            let params: Vec<&str> = ty.params.iter().map(value_type).collect();
+            write!(out, " (param {})", params.join(" ")).unwrap();
+        }
+        if let Some(ref ret) = ty.ret {
+            write!(out, " (result {})", value_type(ret)).unwrap();
+        }
+        out.push_str("))\n");
+    }
+
+    let mut function_imports = 0;
+    for import in module.imports.iter().flat_map(|imports| imports.iter()) {
+        let desc = match import.kind {
+            ImportKind::Function(ref ty) => {
+                function_imports += 1;
+                format!("(func (type {}))", **ty)
+            },
+            ImportKind::Table(ref table) => format!("(table {} anyfunc)", limits(&table.limits)),
+            ImportKind::Memory(ref memory) => format!("(memory {})", limits(&memory.limits)),
+            ImportKind::Global(ref global) => format!("(global {})", global_type(global)),
+        };
+        writeln!(out, "  (import {:?} {:?} {})", import.module, import.field, desc).unwrap();
+    }
+
+    for (index, table) in module.tables.iter().flat_map(|tables| tables.iter()).enumerate() {
+        writeln!(out, "  (table (;{};) {} anyfunc)", index, limits(&table.limits)).unwrap();
+    }
+
+    for (index, memory) in module.memories.iter().flat_map(|memories| memories.iter()).enumerate() {
+        writeln!(out, "  (memory (;{};) {})", index, limits(&memory.limits)).unwrap();
+    }
+
+    for (index, global) in module.globals.iter().flat_map(|globals| globals.iter()).enumerate() {
+        writeln!(out, "  (global (;{};) {} {})", index, global_type(&global.ty), inline_code(&global.init.0)).unwrap();
+    }
+
+    let functions = module.functions.iter().flat_map(|functions| functions.iter());
+    let codes = module.codes.iter().flat_map(|codes| codes.iter());
+    for (index, (function, body)) in functions.zip(codes).enumerate() {
+        writeln!(out, "  (func (;{};) (type {})", function_imports + index, *function.0).unwrap();
+        for local in &body.locals {
+            for _ in 0..local.count {
+                writeln!(out, "    (local {})", value_type(&local.ty)).unwrap();
+            }
+        }
+        write_code(&mut out, 2, &body.code);
+        out.push_str("  )\n");
+    }
+
+    for export in module.exports.iter().flat_map(|exports| exports.iter()) {
+        let desc = match export.kind {
+            ExportKind::Function(ref index) => format!("(func {})", **index),
+            ExportKind::Table(ref index) => format!("(table {})", **index),
+            ExportKind::Memory(ref index) => format!("(memory {})", **index),
+            ExportKind::Global(ref index) => format!("(global {})", **index),
+        };
+        writeln!(out, "  (export {:?} {})", export.field, desc).unwrap();
+    }
+
+    if let Some(ref start) = module.start {
+        writeln!(out, "  (start {})", **start).unwrap();
+    }
+
+    for data in module.data.iter().flat_map(|data| data.iter()) {
+        let bytes: String = data.data.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+        writeln!(
+            out, "  (data (memory {}) (offset {}) \"{}\")",
+            *data.index, inline_code(&data.offset.0), bytes
+        ).unwrap();
+    }
+
+    out.push_str(")\n");
+    out
+}
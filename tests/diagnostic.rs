@@ -0,0 +1,33 @@
+extern crate tifflang;
+
+use std::rc::Rc;
+use tifflang::Diagnostic;
+use tifflang::span::Span;
+
+#[test]
+fn json_with_span_and_file() {
+    let source = "let a = 1\nlet bé = \"x\ny\"\n";
+    let first = source.find("bé").unwrap();
+    let last = source.rfind('"').unwrap() + 1;
+    let span = Span::new((), first, last, Rc::new(source.to_owned()));
+    let diagnostic = Diagnostic::error("E0012", Some(span), "say \"hi\"\n\tthen \\ go")
+        .in_file("dir/a \"b\".tl");
+    assert_eq!(diagnostic.to_json(), concat!(
+        r#"{"file":"dir/a \"b\".tl","#,
+        r#""range":{"start":14,"end":25},"#,
+        r#""start":{"line":2,"column":5},"#,
+        r#""end":{"line":3,"column":3},"#,
+        r#""severity":"error","code":"E0012","#,
+        r#""message":"say \"hi\"\n\tthen \\ go"}"#,
+    ));
+}
+
+#[test]
+fn json_without_span_or_file() {
+    let diagnostic = Diagnostic::warning("W0703", None, "bell \u{7} and return\r");
+    assert_eq!(diagnostic.to_json(), concat!(
+        r#"{"file":null,"range":null,"start":null,"end":null,"#,
+        r#""severity":"warning","code":"W0703","#,
+        r#""message":"bell \u0007 and return\r"}"#,
+    ));
+}
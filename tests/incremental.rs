@@ -0,0 +1,177 @@
+extern crate tifflang;
+
+use tifflang::Session;
+use tifflang::incremental::{Document, Edit};
+
+const SOURCE: &str = r#"import math.{square}
+
+fn foo(x: int, y: int) -> int {
+    let z = x * x
+    z + y * 2
+}
+
+class Point {
+    fn length(x: real, y: real) -> real {
+        x * x + y * y
+    }
+}
+
+fn bar(flag: bool) -> string {
+    if flag { "yes" } else { "no" }
+}
+"#;
+
+fn edit(start: usize, end: usize, text: &str) -> Edit {
+    Edit {
+        start,
+        end,
+        text: text.to_owned(),
+    }
+}
+
+/// Applies the edits one after another, checking after each that the
+/// document matches parsing its whole source again.
+fn check(source: &str, edits: &[Edit]) -> Document {
+    let session = Session::new();
+    let mut document = Document::new("test", source);
+    for edit in edits {
+        document.edit(edit);
+        let expected = session.parse("test", document.source());
+        assert_eq!(
+            format!("{:?}", document.module()),
+            format!("{:?}", expected),
+            "after replacing {}..{} with {:?}, giving:\n{}",
+            edit.start, edit.end, edit.text, document.source()
+        );
+    }
+    document
+}
+
+fn offset(pattern: &str) -> usize {
+    SOURCE.find(pattern).unwrap()
+}
+
+#[test]
+fn edit_inside_function() {
+    let start = offset("y * 2") + 4;
+    let mut document = Document::new("test", SOURCE);
+    let reparsed = document.edit(&edit(start, start + 1, "30"));
+    assert_eq!(reparsed.items, 1);
+    assert_eq!(reparsed.tokens, 1);
+    check(SOURCE, &[edit(start, start + 1, "30")]);
+}
+
+#[test]
+fn insert_and_remove_items() {
+    let at = offset("class");
+    check(SOURCE, &[
+        edit(at, at, "fn added() -> int { 1 }\n\n"),
+        edit(at, at + "fn added() -> int { 1 }\n\n".len(), ""),
+        edit(SOURCE.len(), SOURCE.len(), "fn last() -> int { 2 }"),
+        edit(0, 0, "import util\n"),
+    ]);
+}
+
+#[test]
+fn merge_and_split_items() {
+    let close = offset("}\n\nclass");
+    check(SOURCE, &[
+        edit(SOURCE.len(), SOURCE.len(), "}"),
+        edit(close, close + 1, ""),
+        edit(close, close, "}"),
+        edit(SOURCE.len(), SOURCE.len() + 1, ""),
+    ]);
+}
+
+#[test]
+fn unterminated_string_and_comment() {
+    let at = offset("fn foo");
+    check(SOURCE, &[
+        edit(at, at, "\""),
+        edit(at, at + 1, "/*"),
+        edit(SOURCE.len() + 1, SOURCE.len() + 1, "*/"),
+        edit(at, at + 2, ""),
+    ]);
+}
+
+#[test]
+fn unclosed_parenthesis_changes_newlines() {
+    // inside parentheses line breaks don't end statements, which changes
+    // how the import added at the end parses
+    let at = offset("let z") + 4;
+    let added = "import a\n.b\n";
+    check(SOURCE, &[
+        edit(SOURCE.len(), SOURCE.len(), added),
+        edit(at, at, "("),
+        edit(at, at + 1, ""),
+        edit(offset("class"), offset("class"), "(\n"),
+        edit(offset("class"), offset("class") + 2, ""),
+    ]);
+}
+
+#[test]
+fn edits_between_tokens() {
+    let at = offset("x * x\n    z");
+    check(SOURCE, &[
+        edit(at + 1, at + 2, ""),
+        edit(at + 1, at + 1, "\n"),
+        edit(at + 1, at + 2, "  "),
+        edit(at, at + 1, "xy"),
+    ]);
+}
+
+#[test]
+fn doc_comments() {
+    let module_doc = "//! The module.\n";
+    let doc = "/// Foo.\n";
+    let at = offset("fn foo") + module_doc.len();
+    let body = offset("    z + y") + module_doc.len() + doc.len();
+    check(SOURCE, &[
+        edit(0, 0, module_doc),
+        edit(at, at, doc),
+        edit(at, at, "/"),
+        edit(at, at + 1, ""),
+        edit(body, body, "/// stray\n"),
+        edit(2, 3, "/"),
+        edit(2, 3, "!"),
+        edit(0, 0, "/// not the module\n"),
+    ]);
+}
+
+#[test]
+fn raw_string_completed_later() {
+    let at = offset("z + y");
+    let document = check(SOURCE, &[
+        edit(at, at, "r#"),
+        edit(at + 2, at + 2, "\"hi\"#"),
+    ]);
+    assert_eq!(&document.source()[at..at + 8], "r#\"hi\"#z");
+}
+
+#[test]
+fn random_edits() {
+    const PIECES: &[&str] = &[
+        "", "x", "1", " ", "\n", "{", "}", "(", ")", "\"", "fn", "fn f() -> int { 1 }",
+        "import a.b", "+", ".", ";", "/*", "*/", "//", "0b2", "else", "class C {}",
+        "\"a {x} b\"", "\"{{ {x} }}\"", "\"{\"{1}\"}\"", "'a'", "'", " as char", "{{", "/// d\n", "//!", "r#", "#",
+    ];
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = |bound: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed % bound as u64) as usize
+    };
+
+    let mut source = SOURCE.to_owned();
+    let mut edits = vec![];
+    for _ in 0..300 {
+        let start = next(source.len() + 1);
+        let end = (start + next(6)).min(source.len());
+        let text = PIECES[next(PIECES.len())];
+        source.replace_range(start..end, text);
+        edits.push(edit(start, end, text));
+    }
+    let document = check(SOURCE, &edits);
+    assert_eq!(document.source(), source);
+}
@@ -0,0 +1,273 @@
+extern crate tifflang;
+
+use std::rc::Rc;
+use tifflang::diagnostic;
+use tifflang::lexer::{Lexer, StringPart, Token, Trivia};
+
+/// Writes lossless tokens back out, trivia and all.
+fn relex(source: &str) -> String {
+    let mut text = String::new();
+    for token in Lexer::new().lossless_tokens(Rc::new(source.to_owned())) {
+        for trivia in &token.leading {
+            text.push_str(trivia.text());
+        }
+        text.push_str(token.token.text());
+        for trivia in &token.trailing {
+            text.push_str(trivia.text());
+        }
+    }
+    text
+}
+
+#[test]
+fn lossless_tokens_round_trip() {
+    let sources = [
+        "fn f(x: int) -> int {\n    x + 1 // one more\n}\n",
+        "/* outer /* inner */ still outer */ 1\n/// Doc.\n//! Module doc.\n//// plain\n",
+        "fn f() -> int {\r\n\tlet x = 2\r\n\r\n    x * 3 /* done */\r\n}\r\n",
+        "let s = \"a\\n\\t\\\"{b}\\u{1F600}\" + r#\"raw \"quoted\"\"# + 'c'\n",
+        "let s = \"bad \\q escape\" @ $ 'xy' '' 0x 5i64 3000000000\n",
+        "\"unterminated string\n  to the end",
+        "/* unterminated /* comment */\n1 + 2",
+        "r##\"unterminated raw\"#",
+        "",
+        "   \n\r\n",
+    ];
+    for source in &sources {
+        assert_eq!(relex(source), *source);
+    }
+
+    // the error tokens give up part of what they consume as skipped trivia
+    let skipped = Lexer::new().lossless_tokens(Rc::new(sources[5].to_owned()))
+        .any(|token| token.token.get_value().is_error() &&
+             token.trailing.iter().any(|trivia| *trivia.get_value() == Trivia::Skipped));
+    assert!(skipped);
+}
+
+#[test]
+fn lossless_tokens_skip_newline_tokens() {
+    let tokens: Vec<_> = Lexer::new().lossless_tokens(Rc::new("a\nb\r\nc".to_owned()))
+        .map(|token| token.token.text().to_owned())
+        .collect();
+    assert_eq!(tokens, vec!["a", "b", "c", ""]);
+    assert!(Lexer::new().lossless_tokens(Rc::new("a\nb".to_owned()))
+        .all(|token| !matches!(*token.token.get_value(), Token::Newline)));
+}
+
+/// Each token's debug form and the source text it covers, leaving out the
+/// final `Eof`.
+fn lex(source: &str) -> Vec<(String, String)> {
+    let mut tokens: Vec<_> = Lexer::new().tokens(Rc::new(source.to_owned()))
+        .map(|token| (format!("{:?}", token.get_value()), token.text().to_owned()))
+        .collect();
+    assert_eq!(tokens.pop().map(|(token, _)| token), Some("Eof".to_owned()));
+    tokens
+}
+
+/// The code and covered text of each error token in a source.
+fn errors(source: &str) -> Vec<(&'static str, String)> {
+    Lexer::new().tokens(Rc::new(source.to_owned()))
+        .filter_map(|token| match *token.get_value() {
+            Token::Error(ref err) => Some((err.code(), token.text().to_owned())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The single token in a source.
+fn single(source: &str) -> Token {
+    let mut tokens: Vec<_> = Lexer::new().tokens(Rc::new(source.to_owned())).collect();
+    assert_eq!(tokens.len(), 2, "lexing {:?}", source);
+    tokens.remove(0).get_value().clone()
+}
+
+#[test]
+fn nested_comments() {
+    assert_eq!(lex("1 /* a /* b */ c */ 2"), lex("1 2"));
+    assert_eq!(lex("/*/**/*/x"), lex("x"));
+    assert_eq!(lex("/* a // b */ x"), lex("x"));
+    assert_eq!(lex("/* /* */ */ */"), vec![("Aster".to_owned(), "*".to_owned()), ("Slash".to_owned(), "/".to_owned())]);
+
+    // an unterminated comment is reported at its opening, and swallows the rest
+    assert_eq!(errors("1 /* a /* b */ c\n2"), vec![("E0011", "/*".to_owned())]);
+    assert_eq!(lex("1 /* a /* b */ c\n2").len(), 2);
+}
+
+#[test]
+fn string_escapes() {
+    match single(r#""a\n\t\\\"\'\u{41}\u{1F600}""#) {
+        Token::String(ref string) => assert_eq!(string, "a\n\t\\\"'A\u{1F600}"),
+        token => panic!("unexpected {:?}", token),
+    }
+
+    // only the bad escape is covered by the error
+    assert_eq!(errors(r#"let s = "x \q y""#), vec![("E0013", r"\q".to_owned())]);
+    assert_eq!(errors(r#""\u{110000}""#), vec![("E0013", r"\u{110000}".to_owned())]);
+    assert_eq!(errors(r#""\u{1234567}""#), vec![("E0013", r"\u{1234567}".to_owned())]);
+    assert_eq!(errors(r#""\u{}""#), vec![("E0013", r"\u{}".to_owned())]);
+    assert_eq!(errors(r#""\u41""#), vec![("E0013", r"\u".to_owned())]);
+    assert_eq!(errors(r#""a\é""#), vec![("E0013", r"\é".to_owned())]);
+    // and lexing carries on after the string
+    assert_eq!(lex(r#""\q" x"#).last().unwrap().0, "Ident(\"x\")");
+
+    assert_eq!(errors("\"abc\nx"), vec![("E0012", "\"".to_owned())]);
+}
+
+#[test]
+fn raw_strings() {
+    match single(r#"r"a\n{b}""#) {
+        Token::String(ref string) => assert_eq!(string, r"a\n{b}"),
+        token => panic!("unexpected {:?}", token),
+    }
+    match single(r###"r##"a "# b"##"###) {
+        Token::String(ref string) => assert_eq!(string, r##"a "# b"##),
+        token => panic!("unexpected {:?}", token),
+    }
+    match single("r\"two\nlines\"") {
+        Token::String(ref string) => assert_eq!(string, "two\nlines"),
+        token => panic!("unexpected {:?}", token),
+    }
+
+    // `r` on its own, or before something else, is an identifier
+    assert_eq!(lex("r + r#x")[0].0, "Ident(\"r\")");
+    assert_eq!(lex("r #")[0].0, "Ident(\"r\")");
+
+    // an unclosed raw string is reported at its opening delimiter
+    assert_eq!(errors(r###"r##"a"# b"###), vec![("E0012", "r##\"".to_owned())]);
+}
+
+#[test]
+fn interpolation() {
+    let parts = match single(r#""a {b + 1} c {f("x")}""#) {
+        Token::InterpolatedString(parts) => parts,
+        token => panic!("unexpected {:?}", token),
+    };
+    let parts: Vec<String> = parts.iter()
+        .map(|part| match *part {
+            StringPart::Text(ref text) => format!("{:?}", text),
+            StringPart::Hole(ref tokens) => tokens.iter()
+                .map(|token| format!("{:?}", token.get_value()))
+                .collect::<Vec<_>>()
+                .join(" "),
+        })
+        .collect();
+    assert_eq!(parts, vec![
+        "\"a \"",
+        "Ident(\"b\") Plus Integer(1) Eof",
+        "\" c \"",
+        "Ident(\"f\") ParenLeft String(\"x\") ParenRight Eof",
+    ]);
+
+    // a string without holes is a plain one, and escapes still work around them
+    assert_eq!(lex(r#""abc""#)[0].0, "String(\"abc\")");
+    match single(r#""\t{x}\n""#) {
+        Token::InterpolatedString(ref parts) => {
+            assert!(matches!(parts[0], StringPart::Text(ref text) if text == "\t"));
+            assert!(matches!(parts[2], StringPart::Text(ref text) if text == "\n"));
+        },
+        token => panic!("unexpected {:?}", token),
+    }
+
+    // a hole that's never closed takes the rest of the string with it
+    assert_eq!(errors(r#""{" x"#), vec![("E0012", "\"".to_owned())]);
+}
+
+#[test]
+fn char_literals() {
+    for &(source, ch) in &[("'a'", 'a'), ("'é'", 'é'), (r"'\n'", '\n'), (r"'\''", '\''), (r#"'"'"#, '"'), (r"'\u{1F600}'", '\u{1F600}')] {
+        match single(source) {
+            Token::Char(value) => assert_eq!(value, ch, "lexing {:?}", source),
+            token => panic!("unexpected {:?} lexing {:?}", token, source),
+        }
+    }
+
+    assert_eq!(errors("'ab'"), vec![("E0019", "'ab'".to_owned())]);
+    assert_eq!(errors("''"), vec![("E0019", "''".to_owned())]);
+    assert_eq!(errors("'a"), vec![("E0018", "'".to_owned())]);
+    assert_eq!(errors(r"'\q'"), vec![("E0013", r"\q".to_owned())]);
+}
+
+#[test]
+fn lexing_diagnostics() {
+    let tokens: Vec<_> = Lexer::new().tokens(Rc::new(r#"let s = "a {1 @ 2}" + "\q" $"#.to_owned())).collect();
+    let codes: Vec<_> = diagnostic::collect_tokens(&tokens).iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.span.as_ref().unwrap().text().to_owned()))
+        .collect();
+    assert_eq!(codes, vec![("E0010", "@".to_owned()), ("E0013", r"\q".to_owned()), ("E0010", "$".to_owned())]);
+}
+
+#[test]
+fn number_literals() {
+    let integers = [
+        ("0", 0), ("42", 42), ("1_000_000", 1_000_000), ("0xff", 255), ("0x7FFF_ffff", 2_147_483_647),
+        ("0b1010", 10), ("0b_1_0", 2), ("0o17", 15), ("2147483647", 2_147_483_647), ("7i32", 7), ("0x7fi32", 127),
+    ];
+    for &(source, value) in &integers {
+        match single(source) {
+            Token::Integer(int) => assert_eq!(int, value, "lexing {:?}", source),
+            token => panic!("unexpected {:?} lexing {:?}", token, source),
+        }
+    }
+
+    let reals = [
+        ("1.5", 1.5), ("1e3", 1e3), ("1E3", 1e3), ("1e-5", 1e-5), ("2.5e+3", 2.5e3), ("1_0.2_5", 10.25), ("1e1_0", 1e10),
+        ("2r", 2.0), ("2.0r", 2.0), ("3e2r", 3e2),
+    ];
+    for &(source, value) in &reals {
+        match single(source) {
+            Token::Number(number) => assert_eq!(number, value, "lexing {:?}", source),
+            token => panic!("unexpected {:?} lexing {:?}", token, source),
+        }
+    }
+
+    let longs = [("10i64", 10), ("2147483648i64", 2_147_483_648), ("0xffff_ffff_ffffi64", 0xffff_ffff_ffff), ("9223372036854775807i64", i64::MAX)];
+    for &(source, value) in &longs {
+        match single(source) {
+            Token::Long(int) => assert_eq!(int, value, "lexing {:?}", source),
+            token => panic!("unexpected {:?} lexing {:?}", token, source),
+        }
+    }
+
+    // a `.` or `e` without digits after it isn't part of the number
+    assert_eq!(lex("1.foo").len(), 3);
+    assert_eq!(lex("1e")[0].0, "Error(InvalidSuffix { suffix: \"e\" })");
+}
+
+#[test]
+fn number_literal_errors() {
+    let cases = [
+        ("0b2", "E0014", "2"), ("0o8", "E0014", "8"), ("0b1012", "E0014", "2"), ("12a", "E0016", "a"),
+        ("0x", "E0015", "0x"), ("0b_", "E0015", "0b_"), ("0o", "E0015", "0o"),
+        ("1e_", "E0015", "1e_"), ("1e+_", "E0015", "1e+_"), ("2e_r", "E0015", "2e_"), ("2e+_r", "E0015", "2e+_"),
+        ("5q", "E0016", "q"), ("2.5i32", "E0016", "i32"), ("1e3i64", "E0016", "i64"), ("0x10r", "E0016", "r"), ("1_u8", "E0016", "u8"),
+        ("2147483648", "E0017", "2147483648"), ("0x1_0000_0000", "E0017", "0x1_0000_0000"),
+        ("2147483648i32", "E0017", "2147483648i32"), ("1e400", "E0017", "1e400"),
+        ("9223372036854775808i64", "E0017", "9223372036854775808i64"),
+    ];
+    for &(source, code, text) in &cases {
+        assert_eq!(errors(source), vec![(code, text.to_owned())], "lexing {:?}", source);
+    }
+
+    // the literal is a single token, so lexing carries on after it
+    assert_eq!(lex("1e_ + 2").len(), 3);
+}
+
+#[test]
+fn identifiers() {
+    let names: Vec<_> = lex("größe 名前 _x1 x_ x٣ Δx a\u{200d}b").into_iter().map(|(token, _)| token).collect();
+    assert_eq!(names, vec![
+        "Ident(\"größe\")", "Ident(\"名前\")", "Ident(\"_x1\")", "Ident(\"x_\")", "Ident(\"x٣\")", "Ident(\"Δx\")",
+        "Ident(\"a\\u{200d}b\")",
+    ]);
+
+    // identifiers are compared in NFC, however they were written
+    let decomposed = lex("cafe\u{301}");
+    assert_eq!(decomposed[0].0, lex("caf\u{e9}")[0].0);
+    assert_eq!(decomposed[0].1, "cafe\u{301}");
+    assert!(tifflang::Session::new().check("test", "fn f(caf\u{e9}: int) -> int { cafe\u{301} }").is_ok());
+
+    // characters that can only continue an identifier, or can't be in one
+    assert_eq!(errors("\u{301}x"), vec![("E0010", "\u{301}".to_owned())]);
+    assert_eq!(errors("٣x"), vec![("E0010", "٣".to_owned())]);
+    assert_eq!(errors("a 😀"), vec![("E0010", "😀".to_owned())]);
+}
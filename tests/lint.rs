@@ -0,0 +1,54 @@
+extern crate tifflang;
+
+use tifflang::Session;
+
+/// The line and code of each warning from checking a source, which must
+/// have no errors.
+fn warnings(source: &str) -> Vec<(usize, &'static str)> {
+    let session = Session::new();
+    let program = session.check("test", source).unwrap();
+    session.lint(&program).iter()
+        .map(|diagnostic| (diagnostic.span.as_ref().unwrap().start().line, diagnostic.code))
+        .collect()
+}
+
+#[test]
+fn mixed_script_identifiers() {
+    // the `а` is Cyrillic
+    assert_eq!(warnings("fn f(p\u{430}ypal: int) -> int {\n    p\u{430}ypal\n}"), vec![(1, "W0701")]);
+    assert_eq!(warnings("fn f(x: int) -> int {\n    let \u{3c9}mega = x\n    \u{3c9}mega\n}"), vec![(2, "W0701")]);
+}
+
+#[test]
+fn confusable_identifiers() {
+    // all Cyrillic, but it looks just like `ace`
+    assert_eq!(warnings("fn f(ace: int, \u{430}\u{441}\u{435}: int) -> int { ace }"), vec![(1, "W0702")]);
+    // a Cyrillic `ѕ` is both
+    assert_eq!(warnings("fn f(scope: int, \u{455}cope: int) -> int { scope }"), vec![(1, "W0701"), (1, "W0702")]);
+    // only reported where it first appears
+    assert_eq!(warnings("fn f(ace: int) -> int {\n    let \u{430}\u{441}\u{435} = ace\n    \u{430}\u{441}\u{435} + \u{430}\u{441}\u{435}\n}"), vec![(2, "W0702")]);
+}
+
+#[test]
+fn ordinary_identifiers_dont_warn() {
+    assert_eq!(warnings("fn größe(名前: int, naïve: int, δέλτα: int) -> int { 名前 + naïve + δέλτα }"), vec![]);
+    // ASCII identifiers are never confused with each other
+    assert_eq!(warnings("fn f(l: int, I: int, rn: int, m: int) -> int { l + I + rn + m }"), vec![]);
+    // the same identifier written with a combining accent is the same one
+    assert_eq!(warnings("fn f(caf\u{e9}: int) -> int { cafe\u{301} }"), vec![]);
+}
+
+#[test]
+fn stray_doc_comments() {
+    let source = r#"//! The module.
+/// Documented.
+fn f() -> int {
+    /// Nothing to document.
+    3
+}
+//! Not at the top.
+/// Trailing.
+"#;
+    assert_eq!(warnings(source), vec![(4, "W0703"), (7, "W0703"), (8, "W0703")]);
+    assert_eq!(warnings("//! Module.\n\n/// F.\nfn f() -> int { 1 }\n/// C.\nclass C {\n    /// M.\n    fn m() -> int { 1 }\n}"), vec![]);
+}
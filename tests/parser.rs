@@ -0,0 +1,237 @@
+extern crate tifflang;
+
+use tifflang::Session;
+use tifflang::ast::{Expr, Item, Statement};
+use tifflang::interpreter::Value;
+use tifflang::span::Span;
+
+/// The line and code of each diagnostic from checking a source.
+fn errors(source: &str) -> Vec<(usize, &'static str)> {
+    match Session::new().check("test", source) {
+        Ok(_) => vec![],
+        Err(diagnostics) => diagnostics.iter()
+            .map(|diagnostic| (diagnostic.span.as_ref().unwrap().start().line, diagnostic.code))
+            .collect(),
+    }
+}
+
+/// Writes an expression out with every operator in parentheses.
+fn shape(expr: &Expr) -> String {
+    match *expr {
+        Expr::ConstInteger(int) => int.to_string(),
+        Expr::Variable(ref name) => name.clone(),
+        Expr::BinOp { ref op, ref lhs, ref rhs } =>
+            format!("({} {} {})", shape(&lhs.get_value().value), op, shape(&rhs.get_value().value)),
+        Expr::Cast { ref value, ref to } => format!("({} as {})", shape(&value.get_value().value), to.get_value()),
+        ref expr => panic!("unexpected {:?}", expr),
+    }
+}
+
+/// The shape of an expression, parsed as the body of a function.
+fn parse_shape(expr: &str) -> String {
+    let module = Session::new().parse("test", &format!("fn f(a: int, b: int, c: int) -> int {{ {} }}", expr));
+    match *module.items[0].get_value() {
+        Item::Function { ref body, .. } => match *body[0].get_value() {
+            Statement::Expr(ref expr) => shape(&expr.value),
+            ref stmt => panic!("unexpected {:?}", stmt),
+        },
+        ref item => panic!("unexpected {:?}", item),
+    }
+}
+
+#[test]
+fn operator_precedence() {
+    assert_eq!(parse_shape("10 - 3 - 2"), "((10 - 3) - 2)");
+    assert_eq!(parse_shape("8 / 4 / 2"), "((8 / 4) / 2)");
+    assert_eq!(parse_shape("a + b * c"), "(a + (b * c))");
+    assert_eq!(parse_shape("a * b + c"), "((a * b) + c)");
+    assert_eq!(parse_shape("a - b + c - 1"), "(((a - b) + c) - 1)");
+    assert_eq!(parse_shape("a * b / c * 2"), "(((a * b) / c) * 2)");
+    assert_eq!(parse_shape("a + b as real * c"), "(a + ((b as real) * c))");
+    assert_eq!(parse_shape("a as real as int"), "((a as real) as int)");
+}
+
+#[test]
+fn operator_results() {
+    let run = |expr: &str| match Session::new().run("test", &format!("fn f() -> int {{ {} }}", expr), "f", &[]) {
+        Ok(Value::Integer(int)) => int,
+        result => panic!("unexpected {:?}", result),
+    };
+    assert_eq!(run("10 - 3 - 2"), 5);
+    assert_eq!(run("8 / 4 / 2"), 1);
+    assert_eq!(run("20 - 2 * 3 - 8 / 2 / 2"), 12);
+}
+
+#[test]
+fn recovers_from_syntax_errors() {
+    let source = r#"fn first(x: int, y: ) -> int {
+    let = 3
+    x * ) + 2
+    y
+}
+
+class C {
+    5
+    fn good(a: int) -> int { a }
+}
+
+fn second(a: int) int {
+    a +* 1
+}
+}
+import a.{b c}
+fn last(x: int) -> int { x }
+"#;
+    assert_eq!(errors(source), vec![
+        (1, "E0100"),
+        (2, "E0100"),
+        (3, "E0100"),
+        (8, "E0100"),
+        (12, "E0100"),
+        (13, "E0100"),
+        (15, "E0100"),
+        (16, "E0100"),
+    ]);
+
+    // the parts around the mistakes are kept
+    let module = Session::new().parse("test", source);
+    let names: Vec<&str> = module.items.iter()
+        .filter_map(|item| match *item.get_value() {
+            Item::Function { ref name, .. } | Item::Class { ref name, .. } => Some(&name.get_value()[..]),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, vec!["first", "C", "second", "last"]);
+}
+
+#[test]
+fn unclosed_brackets() {
+    // running out of source is reported at the bracket left open
+    assert_eq!(errors("fn f() -> int {"), vec![(1, "E0102")]);
+    assert_eq!(errors("class C {\n    fn f() -> int { 1 }\n"), vec![(1, "E0102")]);
+    assert_eq!(errors("fn f(x: int) -> int {\n    x +\n"), vec![(1, "E0102")]);
+    assert_eq!(errors("fn f() -> int {\n    g(1,\n"), vec![(2, "E0206"), (2, "E0102")]);
+    assert_eq!(errors("fn f(\n"), vec![(1, "E0102")]);
+    assert_eq!(errors("fn"), vec![(1, "E0100")]);
+}
+
+#[test]
+fn source_ending_anywhere() {
+    let source = r#"import a.{b, c}
+/// Doc.
+class C {
+    fn f(x: int, y: real) -> int {
+        let z = if x { g(x, "{x as char}") } else { 2 }
+        z * 3
+    }
+}
+"#;
+    let session = Session::new();
+    for (end, _) in source.char_indices() {
+        session.parse("test", &source[..end]);
+    }
+}
+
+#[test]
+fn reports_errors_skipped_while_recovering() {
+    // the lexing errors after `let =` are skipped over with the rest of
+    // the statement, but still reported
+    assert_eq!(errors("fn f() -> int {\n  let = 3 @ $\n  1\n}"), vec![(2, "E0100"), (2, "E0010"), (2, "E0010")]);
+    assert_eq!(errors("fn f(x: int, @) -> int { x \"{1 $}\" # }"), vec![(1, "E0010"), (1, "E0100"), (1, "E0010"), (1, "E0010")]);
+    // one the parser ran into itself is only reported once
+    assert_eq!(errors("fn f() -> int { @ }"), vec![(1, "E0010")]);
+}
+
+/// The statements in the body of a function with arguments `a`, `b` and
+/// `c`, ignoring any errors.
+fn body(statements: &str) -> Vec<Statement> {
+    let source = format!("fn f(a: int, b: int, c: bool) -> int {{\n{}\n}}", statements);
+    match Session::new().parse("test", &source).items.pop().unwrap().value {
+        Item::Function { body, .. } => body.into_iter().map(|stmt| stmt.value).collect(),
+        item => panic!("unexpected {:?}", item),
+    }
+}
+
+#[test]
+fn statement_terminators() {
+    assert_eq!(body("a; b").len(), 2);
+    assert_eq!(body("a\nb").len(), 2);
+    assert_eq!(body("a;\n\n;b;").len(), 2);
+
+    // a line ending where a statement can't, or one starting where a
+    // statement can't, continues the statement
+    match body("let x = a +\n    b\nx")[..] {
+        [Statement::Let { ref value, .. }, Statement::Expr(_)] => assert_eq!(shape(&value.get_value().value), "(a + b)"),
+        ref statements => panic!("unexpected {:?}", statements),
+    }
+    match body("if c {\n    a\n}\nelse {\n    b\n}")[..] {
+        [Statement::Expr(ref expr)] => match expr.value {
+            Expr::If { ref branch_else, .. } => assert_eq!(branch_else.len(), 1),
+            ref expr => panic!("unexpected {:?}", expr),
+        },
+        ref statements => panic!("unexpected {:?}", statements),
+    }
+    assert_eq!(body("f(a,\n    b)").len(), 1);
+    assert_eq!(body("a\n    as real").len(), 1);
+}
+
+#[test]
+fn ambiguous_line_starts() {
+    let check = |statements: &str| errors(&format!("fn f(foo: int, a: int, b: int) -> int {{\n{}\n}}", statements));
+    assert_eq!(check("let y = foo\n(a + b)\ny"), vec![(3, "E0101")]);
+    assert_eq!(check("let y = foo\n- a\ny"), vec![(3, "E0101")]);
+    assert_eq!(check("let y = foo\n    * b\ny"), vec![(3, "E0101")]);
+    assert_eq!(check("let y = foo -\n    a\ny"), vec![]);
+    assert_eq!(check("let y = foo; -a"), vec![(2, "E0100")]);
+}
+
+/// The text of a doc comment, if there is one.
+fn doc_text(doc: &Option<Span<String>>) -> Option<&str> {
+    doc.as_ref().map(|doc| &doc.get_value()[..])
+}
+
+#[test]
+fn doc_comments() {
+    let source = r#"//! The module.
+//! Second line.
+
+/// Adds.
+///   Indented.
+fn f() -> int { 1 }
+
+/// A class.
+class C {
+    /// A method.
+    fn m() -> int { 2 }
+    fn undocumented() -> int { 3 }
+}
+//// Not a doc comment.
+fn g() -> int { 4 }
+"#;
+    let module = Session::new().parse("test", source);
+    assert_eq!(doc_text(&module.doc), Some("The module.\nSecond line."));
+    match *module.items[0].get_value() {
+        Item::Function { ref doc, .. } => assert_eq!(doc_text(doc), Some("Adds.\n  Indented.")),
+        ref item => panic!("unexpected {:?}", item),
+    }
+    match *module.items[1].get_value() {
+        Item::Class { ref doc, ref members, .. } => {
+            assert_eq!(doc_text(doc), Some("A class."));
+            let docs: Vec<_> = members.iter()
+                .map(|member| match *member.get_value() {
+                    Item::Function { ref doc, .. } => doc_text(doc),
+                    ref item => panic!("unexpected {:?}", item),
+                })
+                .collect();
+            assert_eq!(docs, vec![Some("A method."), None]);
+        },
+        ref item => panic!("unexpected {:?}", item),
+    }
+    match *module.items[2].get_value() {
+        Item::Function { ref doc, .. } => assert_eq!(doc_text(doc), None),
+        ref item => panic!("unexpected {:?}", item),
+    }
+
+    // without `//!` at the top, the module has no doc
+    assert!(Session::new().parse("test", "/// F.\nfn f() -> int { 1 }").doc.is_none());
+}
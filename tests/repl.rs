@@ -0,0 +1,41 @@
+extern crate tifflang;
+
+use tifflang::{Repl, Session};
+
+#[test]
+fn int_arithmetic_matches_compiled_code() {
+    let exprs = [
+        "2147483647 + 1", "0 - 2147483647 - 2", "65536 * 65536", "2147483647 * 3", "7 / 2",
+        // constants whose last encoded byte has its sign bit set
+        "64", "0 - 65", "8191 + 0", "'a' as int",
+    ];
+    for expr in &exprs {
+        let compiled = Session::new().run("test", &format!("fn f() -> int {{ {} }}", expr), "f", &[]).unwrap();
+        let evaluated = Repl::new().eval(expr).unwrap();
+        assert_eq!(evaluated[0].to_string(), format!("{}: int", compiled), "evaluating {}", expr);
+    }
+}
+
+#[test]
+fn waits_for_the_rest_of_a_statement() {
+    let repl = Repl::new();
+    for input in &["let y = 1 +\n", "(1,\n", "fn f() -> int {\n", "let y =\n", "1 /* two\n", "if 1 { 2 } else\n"] {
+        assert!(repl.needs_more_input(input), "input {:?}", input);
+    }
+    for input in &["", "\n", "let y = 1 + 2\n", "1;\n", "f(1)\n", "/// Doc.\n", "x as int\n"] {
+        assert!(!repl.needs_more_input(input), "input {:?}", input);
+    }
+}
+
+#[test]
+fn i64_arithmetic_matches_compiled_code() {
+    let exprs = [
+        ("3000000000i64 * 3i64", "i64"), ("9223372036854775807i64 + 1i64", "i64"), ("0i64 - 7i64 / 2i64", "i64"), ("64i64", "i64"),
+        ("2147483647 as i64 + 1i64", "i64"), ("4294967297i64 as int", "int"), ("\"{0i64 - 5000000000i64}\"", "string"),
+    ];
+    for &(expr, result) in &exprs {
+        let compiled = Session::new().run("test", &format!("fn f() -> {} {{ {} }}", result, expr), "f", &[]).unwrap();
+        let evaluated = Repl::new().eval(expr).unwrap();
+        assert_eq!(evaluated[0].to_string(), format!("{}: {}", compiled, result), "evaluating {}", expr);
+    }
+}
@@ -0,0 +1,19 @@
+extern crate tifflang;
+
+use std::rc::Rc;
+use tifflang::span::Span;
+
+#[test]
+fn multiline_span_with_crlf() {
+    let source = "fn f(x: int) -> int {\r\n    if x { 1 }\r\n    else { 2 }é\r\n}\r\n";
+    let first = source.find("if").unwrap();
+    let last = source.find('é').unwrap();
+    let span = Span::new("message", first, last, Rc::new(source.to_owned()));
+    assert_eq!(format!("{}", span), concat!(
+        "message\n",
+        "-->     if x { 1 }\n",
+        "        ^^^^^^^^^^\n",
+        "-->     else { 2 }é\n",
+        "    ^^^^^^^^^^^^^^\n",
+    ));
+}